@@ -85,7 +85,10 @@ pub fn parse_player(
             ))
         } else if player_type == "pvsearch" {
             let mut pvsearch_options = Options::new();
-            pvsearch_options.opt("d", "depth").opt("g", "goal");
+            pvsearch_options
+                .opt("d", "depth")
+                .opt("g", "goal")
+                .opt("b", "book");
 
             let matches = pvsearch_options.parse(&matches.free);
 
@@ -93,28 +96,48 @@ pub fn parse_player(
                 return Err(String::from("Both depth and goal were specified."));
             }
 
-            Ok((
-                Some(Box::new(if let Some(depth) = matches.opt_str("depth") {
-                    if let Ok(depth) = u8::from_str(&depth) {
-                        player::PvSearchPlayer::with_depth(depth)
+            let pvsearch_player = if let Some(depth) = matches.opt_str("depth") {
+                if let Ok(depth) = u8::from_str(&depth) {
+                    player::PvSearchPlayer::with_depth(depth)
+                } else {
+                    return Err(String::from("Invalid depth."));
+                }
+            } else {
+                let goal = if let Some(goal) = matches.opt_str("goal") {
+                    if let Ok(goal) = u16::from_str(&goal) {
+                        goal
                     } else {
-                        return Err(String::from("Invalid depth."));
+                        return Err(String::from("Invalid goal."));
                     }
                 } else {
-                    let goal = if let Some(goal) = matches.opt_str("goal") {
-                        if let Ok(goal) = u16::from_str(&goal) {
-                            goal
-                        } else {
-                            return Err(String::from("Invalid goal."));
-                        }
-                    } else {
-                        60
-                    };
+                    60
+                };
 
-                    player::PvSearchPlayer::with_goal(goal)
-                })),
-                matches.free,
-            ))
+                player::PvSearchPlayer::with_goal(goal)
+            };
+
+            if let Some(file_name) = matches.opt_str("book") {
+                pvsearch_player.set_opening_book(player::OpeningBook::open(&file_name)?);
+            }
+
+            Ok((Some(Box::new(pvsearch_player)), matches.free))
+        } else if player_type == "random" {
+            let mut random_options = Options::new();
+            random_options.opt("r", "seed");
+
+            let matches = random_options.parse(&matches.free);
+
+            let random_player = if let Some(seed) = matches.opt_str("seed") {
+                if let Ok(seed) = u64::from_str(&seed) {
+                    player::RandomPlayer::with_seed(seed)
+                } else {
+                    return Err(String::from("Invalid seed."));
+                }
+            } else {
+                player::RandomPlayer::new()
+            };
+
+            Ok((Some(Box::new(random_player)), matches.free))
         } else if player_type == "playtak" {
             let mut playtak_options = Options::new();
             playtak_options
@@ -0,0 +1,64 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::analysis::search::PvSearch;
+use zero_sum::impls::tak::State;
+
+use super::{logger, tps, Game, SearchResult};
+use crate::player::{self, WeightedEvaluator};
+
+// One-shot analysis for scripts and batch jobs, without the TEI protocol
+
+// Reads a position given as TPS, or as a PTN game, which is played through to its last ply
+pub fn parse_position(position: &str) -> Result<State, String> {
+    match tps::from_tps(position.trim()) {
+        Ok(state) => Ok(state),
+        Err(tps_error) => match logger::parse_ptn(position) {
+            Ok((header, plies)) => Game::into_state(header, plies),
+            Err(ptn_error) => Err(format!(
+                "Not a TPS position ({}) or a PTN game ({}).",
+                tps_error, ptn_error
+            )),
+        },
+    }
+}
+
+pub fn analyze(state: &State, depth: u8) -> Result<SearchResult, String> {
+    let mut pvsearch = PvSearch::with_depth(WeightedEvaluator::default(), depth);
+
+    match player::search_position(&mut pvsearch, state, None) {
+        Some((result, _)) => Ok(result),
+        None => Err(String::from("There is no move to analyze.")),
+    }
+}
+
+// One field per line, so a script can pick out the ones it needs
+pub fn print_analysis(result: &SearchResult) {
+    let pv_ptn = result
+        .principal_variation
+        .iter()
+        .map(|ply| ply.to_ptn())
+        .collect::<Vec<_>>();
+
+    println!("bestmove {}", result.best_ply.to_ptn());
+    println!("score {}", result.score);
+    println!("depth {}", result.depth);
+    println!("time {}", result.elapsed.as_millis());
+    println!("pv {}", pv_ptn.join(" "));
+}
@@ -0,0 +1,97 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::fmt::Write;
+
+use zero_sum::impls::tak::{Color, State};
+
+use super::{resolution, rules, tps};
+
+// The position as TPS, then for each color a grid of the squares it controls and a grid per road
+// group, drawn with '#' and '.' from the top row down.  zero_sum's own bitmaps are private, so the
+// groups here are recomputed from the board; comparing them against check_resolution's decisions
+// is the point.
+pub fn dump_debug(state: &State) -> String {
+    let board_size = state.board.len();
+    let mut dump = String::new();
+
+    writeln!(dump, "{}", tps::to_tps(state)).ok();
+
+    for &color in [Color::White, Color::Black].iter() {
+        let name = rules::color_name(color);
+
+        let controlled = rules::controlled_squares(state, color);
+        writeln!(dump, "\n{} pieces:", name).ok();
+        dump.push_str(&grid(board_size, |x, y| controlled[x][y]));
+
+        for (index, group) in resolution::road_groups(state, color).iter().enumerate() {
+            writeln!(
+                dump,
+                "\n{} road group {}{}:",
+                name,
+                index + 1,
+                if resolution::is_road(group, board_size) {
+                    " (road)"
+                } else {
+                    ""
+                },
+            )
+            .ok();
+            dump.push_str(&grid(board_size, |x, y| group.contains(&(x, y))));
+        }
+    }
+
+    dump
+}
+
+// A one-line account of the position for compact logs, e.g. "ply 14, W: 16F 1C, B: 15F 1C, flats
+// 8-7".  The pieces are each color's reserves, and the flats are the stacks each color's
+// flatstones top.
+pub fn summary(state: &State) -> String {
+    let (white_flats, black_flats) = resolution::flat_counts(state);
+    let reserves = |color| {
+        let (flatstones, capstones) = rules::remaining_pieces(state, color);
+        format!("{}F {}C", flatstones, capstones)
+    };
+
+    format!(
+        "ply {}, W: {}, B: {}, flats {}-{}",
+        state.ply_count,
+        reserves(Color::White),
+        reserves(Color::Black),
+        white_flats,
+        black_flats,
+    )
+}
+
+fn grid<F>(board_size: usize, filled: F) -> String
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let mut grid = String::with_capacity((board_size + 1) * board_size);
+
+    for y in (0..board_size).rev() {
+        for x in 0..board_size {
+            grid.push(if filled(x, y) { '#' } else { '.' });
+        }
+        grid.push('\n');
+    }
+
+    grid
+}
@@ -18,6 +18,7 @@
 //
 
 use std::fmt;
+use std::str::FromStr;
 
 use time;
 
@@ -32,6 +33,7 @@ pub struct Header {
     pub result: String,
     pub size: usize,
     pub tps: String,
+    pub komi: i32,
 }
 
 impl Header {
@@ -49,6 +51,15 @@ impl Header {
             result: String::new(),
             size: 5,
             tps: String::new(),
+            komi: 0,
+        }
+    }
+
+    // Reads a komi written in flats, e.g. "2" or "2.5", into half-flats
+    pub fn parse_komi(komi: &str) -> Option<i32> {
+        match f32::from_str(komi) {
+            Ok(komi) if komi >= 0.0 && (komi * 2.0).fract() == 0.0 => Some((komi * 2.0) as i32),
+            _ => None,
         }
     }
 }
@@ -83,6 +94,10 @@ impl fmt::Display for Header {
             write!(f, "\r\n[TPS \"{}\"]", self.tps).ok();
         }
 
+        if self.komi != 0 {
+            write!(f, "\r\n[Komi \"{}\"]", self.komi as f32 / 2.0).ok();
+        }
+
         result
     }
 }
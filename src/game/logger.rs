@@ -25,7 +25,7 @@ use std::str::{Chars, FromStr};
 
 use zero_sum::impls::tak::{Color, Ply, State};
 
-use super::{Game, Header};
+use super::{resolution, tps, Game, Header};
 
 static GAMES_LOG: &'static str = "games.log";
 static GAMES_LOG_TMP: &'static str = "games.log.tmp";
@@ -190,6 +190,11 @@ fn parse_header(source: &mut Peekable<Chars>) -> Option<Header> {
             }
         } else if name == "TPS" {
             header.tps = value;
+        } else if name == "Komi" {
+            match Header::parse_komi(&value) {
+                Some(komi) => header.komi = komi,
+                None => return None,
+            }
         }
 
         advance_whitespace(source, true);
@@ -204,12 +209,13 @@ fn parse_header(source: &mut Peekable<Chars>) -> Option<Header> {
         return None;
     }
 
-    if let Some(state) = State::from_tps(&format!("[TPS \"{}\"]", header.tps)) {
-        if state.board.len() != header.size {
-            return None;
+    if !header.tps.is_empty() {
+        match tps::from_tps(&header.tps) {
+            Ok(state) => if state.board.len() != header.size {
+                return None;
+            },
+            Err(_) => return None,
         }
-    } else if !header.tps.is_empty() {
-        return None;
     }
 
     Some(header)
@@ -293,7 +299,8 @@ fn parse_plies(source: &mut Peekable<Chars>, turn_offset: usize) -> Option<Vec<P
             };
         }
 
-        while peek_char == '\'' ||
+        while peek_char == '*' ||
+              peek_char == '\'' ||
               peek_char == '?' ||
               peek_char == '!' {
             source.next();
@@ -399,7 +406,7 @@ fn parse_game(source: &mut Peekable<Chars>) -> Option<(Header, Vec<Ply>)> {
     let turn_offset = if header.tps.is_empty() {
         0
     } else {
-        State::from_tps(&format!("[TPS \"{}\"]", header.tps)).unwrap().ply_count as usize / 2
+        tps::from_tps(&header.tps).unwrap().ply_count as usize / 2
     };
 
     let plies = match parse_plies(source, turn_offset) {
@@ -457,28 +464,63 @@ pub fn open_ptn_file(file_name: &str) -> Result<(Header, Vec<Ply>), String> {
             let mut data = String::new();
             file.read_to_string(&mut data).ok();
 
-            let mut source = data.chars().peekable();
-
-            match parse_game(&mut source) {
-                Some((header, plies)) => {
-                    let game = Game {
-                        header: header,
-                        plies: plies,
-                        p1: None, p2: None, p1_sender: None, p2_sender: None,
-                    };
-                    if game.to_state().is_ok() {
-                        Ok((game.header, game.plies))
-                    } else {
-                        Err(String::from("Invalid PTN"))
-                    }
-                },
-                None => Err(String::from("Invalid PTN")),
-            }
+            parse_ptn(&data)
         },
         _ => Err(String::from("Cannot open PTN file")),
     }
 }
 
+// Reads a PTN game: its tags, then its numbered moves, skipping comments and the result token
+pub fn parse_ptn(data: &str) -> Result<(Header, Vec<Ply>), String> {
+    let mut source = data.chars().peekable();
+
+    match parse_game(&mut source) {
+        Some((header, plies)) => {
+            let game = Game {
+                header: header,
+                plies: plies,
+                p1: None, p2: None, p1_sender: None, p2_sender: None,
+            };
+            match game.to_state() {
+                Ok(_) => Ok((game.header, game.plies)),
+                Err(error) => Err(format!("Invalid PTN: {}", error)),
+            }
+        },
+        None => Err(String::from("Invalid PTN")),
+    }
+}
+
+// The state at the end of a PTN game
+pub fn state_from_ptn(data: &str) -> Result<State, String> {
+    let (header, plies) = parse_ptn(data)?;
+    Game::into_state(header, plies)
+}
+
+// Writes a game out as PTN, with its result taken from the final state and scored with komi, in
+// half-flats as in Header
+pub fn write_ptn(size: usize, plies: &[Ply], p1: Option<&str>, p2: Option<&str>, komi: i32)
+    -> Result<String, String> {
+    let mut header = Header::new();
+    header.size = size;
+    header.komi = komi;
+    header.p1 = p1.unwrap_or("").to_string();
+    header.p2 = p2.unwrap_or("").to_string();
+
+    let mut game = Game {
+        header: header,
+        plies: plies.to_vec(),
+        p1: None, p2: None, p1_sender: None, p2_sender: None,
+    };
+
+    let state = game.to_state()?;
+    if let Some(resolution) = resolution::game_result(&state) {
+        let resolution = resolution::apply_komi(&state, resolution, game.header.komi);
+        game.header.result = String::from(resolution::to_ptn_result(&resolution));
+    }
+
+    Ok(format!("{}", game))
+}
+
 pub fn read_tmp_file() -> Result<(Header, Vec<Ply>), String> {
     open_ptn_file(GAMES_LOG_TMP)
 }
@@ -501,3 +543,34 @@ pub fn finalize_tmp_file() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_game_and_its_komi_survive_export_and_reimport() {
+        let source = concat!(
+            "[Size \"5\"]\r\n",
+            "[Komi \"1.5\"]\r\n",
+            "\r\n",
+            " 1. a1      e5\r\n",
+            " 2. c3      c4\r\n",
+            " 3. c3+     d4\r\n",
+            "\r\n",
+        );
+        let (header, plies) = parse_ptn(source).unwrap();
+        assert_eq!(header.komi, 3);
+        assert_eq!(plies.len(), 6);
+
+        let exported =
+            write_ptn(header.size, &plies, Some("Alice"), Some("Bob"), header.komi).unwrap();
+        let (reimported, reimported_plies) = parse_ptn(&exported).unwrap();
+
+        assert_eq!(reimported.size, header.size);
+        assert_eq!(reimported.komi, header.komi);
+        assert_eq!(reimported.p1, "Alice");
+        assert_eq!(reimported.p2, "Bob");
+        assert!(reimported_plies == plies);
+    }
+}
@@ -17,17 +17,145 @@
 // Copyright 2016-2017 Chris Foster
 //
 
+use std::time::Duration;
+
 use zero_sum::impls::tak::{Color, Ply, State};
 
+use super::rules;
+use crate::player::Bound;
+
 #[derive(Clone, Debug)]
 pub enum Message {
     GameStart(Color),
     GameOver,
     MoveRequest(State),
+    MoveRequestLimited(State, SearchLimit),
     MoveResponse(Ply),
+    MoveResponseSearch(SearchResult),
+    // Several candidate lines, best first, when more than one was asked for
+    MoveResponseMultiPv(Vec<SearchResult>),
+    StopSearch,
     UndoRequest,
     UndoAccept,
     UndoRemove,
     //Chat(String),
     Special(String),
 }
+
+// Everything a search reports about its decision, so that front-ends can show more than the move
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_ply: Ply,
+    // From the side to move's point of view, in centi-flats: a flat is worth 100, a draw is 0, and
+    // a road scores ROAD_WIN less the plies until it's made (see plies_to_road)
+    pub score: i32,
+    // Whether score is exact, or only a bound on the true score, e.g. after a fail-high
+    pub bound: Bound,
+    pub depth: u32,
+    // Starts with best_ply, and contains only plies that are legal in sequence from the root
+    pub principal_variation: Vec<Ply>,
+    // None when the search doesn't count its nodes
+    pub nodes: Option<u64>,
+    pub elapsed: Duration,
+}
+
+impl SearchResult {
+    pub fn nodes_per_second(&self) -> Option<u64> {
+        let millis = self.elapsed.as_millis().max(1) as u64;
+        self.nodes.map(|nodes| nodes * 1000 / millis)
+    }
+}
+
+// Overrides the player's usual search settings for a single move request
+#[derive(Clone, Debug)]
+pub enum SearchLimit {
+    Time(Duration),
+    Depth(u8),
+    // Stops once the search has visited about this many nodes
+    Nodes(u64),
+    // Searches until StopSearch arrives
+    Infinite,
+}
+
+// How long a player thinks about each move, separate from how it searches
+#[derive(Clone, Debug)]
+pub enum TimePolicy {
+    MoveTime(Duration),
+    // The player's time left and its increment per move.  Each move gets an even share of what's
+    // left over the plies the game is expected to last, plus the increment.
+    Clock {
+        remaining: Duration,
+        increment: Duration,
+    },
+    // Searches until StopSearch arrives
+    Infinite,
+}
+
+impl TimePolicy {
+    pub fn limit(&self, state: &State) -> SearchLimit {
+        match *self {
+            TimePolicy::MoveTime(time) => SearchLimit::Time(time),
+            TimePolicy::Clock {
+                remaining,
+                increment,
+            } => {
+                let share = remaining / (rules::estimate_remaining_plies(state) + 2) as u32;
+                SearchLimit::Time(share + increment)
+            }
+            TimePolicy::Infinite => SearchLimit::Infinite,
+        }
+    }
+
+    // Takes the time a move took off the clock and adds the increment, as the game's clock does
+    pub fn charge(&mut self, elapsed: Duration) {
+        if let TimePolicy::Clock {
+            ref mut remaining,
+            increment,
+        } = *self
+        {
+            *remaining = remaining.checked_sub(elapsed).unwrap_or_default() + increment;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_clock_is_charged_for_each_move() {
+        let mut policy = TimePolicy::Clock {
+            remaining: Duration::from_secs(60),
+            increment: Duration::from_secs(2),
+        };
+
+        policy.charge(Duration::from_secs(10));
+        match policy {
+            TimePolicy::Clock { remaining, .. } => assert_eq!(remaining, Duration::from_secs(52)),
+            _ => unreachable!(),
+        }
+
+        // Overrunning the clock leaves only the increment
+        policy.charge(Duration::from_secs(100));
+        match policy {
+            TimePolicy::Clock { remaining, .. } => assert_eq!(remaining, Duration::from_secs(2)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn a_clock_budget_shrinks_as_time_is_spent() {
+        let state = rules::new_state(5).unwrap();
+        let mut policy = TimePolicy::Clock {
+            remaining: Duration::from_secs(60),
+            increment: Duration::from_secs(0),
+        };
+
+        let before = policy.limit(&state);
+        policy.charge(Duration::from_secs(30));
+        match (before, policy.limit(&state)) {
+            (SearchLimit::Time(before), SearchLimit::Time(after)) => assert!(after < before),
+            _ => unreachable!(),
+        }
+    }
+}
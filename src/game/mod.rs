@@ -28,12 +28,23 @@ use zero_sum::State as StateTrait;
 use crate::player::{self, Player};
 
 pub use self::header::Header;
-pub use self::message::Message;
+pub use self::message::{Message, SearchLimit, SearchResult, TimePolicy};
 
+pub mod analyze;
+pub mod debug;
 mod header;
 pub mod logger;
 mod message;
+pub mod movegen;
+pub mod ptn;
+pub mod replay;
+pub mod resolution;
+pub mod rules;
+pub mod self_play;
+pub mod symmetry;
 pub mod tei;
+pub mod tps;
+pub mod zobrist;
 
 pub struct Game {
     pub header: Header,
@@ -71,20 +82,36 @@ impl Game {
     }
 
     pub fn to_state(&self) -> Result<State, String> {
-        let mut state = if self.header.tps.is_empty() {
-            State::new(self.header.size)
-        } else {
-            if let Some(state) = State::from_tps(&format!("[TPS \"{}\"]", self.header.tps)) {
-                state
-            } else {
-                return Err(String::from("Invalid TPS."));
-            }
-        };
+        let mut state = self.initial_state()?;
+
+        match rules::execute_plies_indexed(&mut state, &self.plies) {
+            Ok(_) => Ok(state),
+            Err((index, error)) => Err(format!(
+                "ply {} ({}): {}",
+                index + 1,
+                self.plies[index].to_ptn(),
+                error,
+            )),
+        }
+    }
 
-        if let Err(error) = state.execute_plies(&self.plies) {
-            Err(error)
+    // Takes back the last ply, leaving the game as it was before the ply was played.  The state is
+    // rebuilt from the remaining plies, so a standing stone that a capstone flattened stands again.
+    pub fn undo_ply(&mut self) -> Option<Ply> {
+        self.plies.pop()
+    }
+
+    // The hash of every position in the game so far, starting with the initial state
+    pub fn position_history(&self) -> Result<Vec<u64>, String> {
+        zobrist::position_history(&self.initial_state()?, &self.plies)
+    }
+
+    // The state before any plies are played
+    pub fn initial_state(&self) -> Result<State, String> {
+        if self.header.tps.is_empty() {
+            rules::new_state(self.header.size)
         } else {
-            Ok(state)
+            tps::from_tps(&self.header.tps)
         }
     }
 
@@ -231,29 +258,28 @@ impl Game {
         {
             let state = self.to_state().unwrap();
 
-            self.send_message(
-                if state.ply_count % 2 == 0 {
-                    Color::White
-                } else {
-                    Color::Black
-                },
-                Message::MoveRequest(state),
-            );
+            self.send_message(rules::side_to_move(&state), Message::MoveRequest(state));
         }
 
         let mut undo_requested = None;
 
         // Run game
         for (color, message) in receiver.iter() {
+            // Only the best ply of a search result gets played
+            let message = match message {
+                Message::MoveResponseSearch(result) => Message::MoveResponse(result.best_ply),
+                Message::MoveResponseMultiPv(mut lines) if !lines.is_empty() => {
+                    Message::MoveResponse(lines.swap_remove(0).best_ply)
+                }
+                message => message,
+            };
+
             match message {
                 Message::MoveResponse(ply) => {
-                    if color
-                        != if self.plies.len() % 2 == 0 {
-                            Color::White
-                        } else {
-                            Color::Black
-                        }
-                    {
+                    let mut state = self.to_state().unwrap();
+
+                    // A game from a TPS start can begin with either side to move
+                    if color != rules::side_to_move(&state) {
                         continue;
                     }
 
@@ -263,9 +289,9 @@ impl Game {
                         }
                     }
 
-                    let mut state = self.to_state().unwrap();
+                    let result = rules::execute_validated(&mut state, &ply);
 
-                    if state.execute_ply(Some(&ply)).is_ok() {
+                    if result.is_ok() {
                         self.plies.push(ply.clone());
                         print_game(self);
 
@@ -273,24 +299,28 @@ impl Game {
 
                         self.send_message(color.flip(), Message::MoveResponse(ply));
 
-                        if let Some(resolution) = state.check_resolution() {
-                            self.header.result = String::from(match resolution {
-                                Resolution::Road(color) => {
-                                    if color == Color::White {
-                                        "R-0"
-                                    } else {
-                                        "0-R"
-                                    }
-                                }
-                                Resolution::Flat(color) => {
-                                    if color == Color::White {
-                                        "F-0"
-                                    } else {
-                                        "0-F"
-                                    }
-                                }
-                                Resolution::Draw => "1/2-1/2",
-                            });
+                        let repetition = zobrist::is_repetition_draw(
+                            &self.position_history().unwrap(),
+                            zobrist::REPETITION_LIMIT,
+                        );
+
+                        if let Some(resolution) = resolution::game_result(&state) {
+                            let resolution =
+                                resolution::apply_komi(&state, resolution, self.header.komi);
+
+                            self.header.result =
+                                String::from(resolution::to_ptn_result(&resolution));
+
+                            logger::write_tmp_file(self);
+                            logger::finalize_tmp_file();
+
+                            self.send_message(color.flip(), Message::GameOver);
+                            self.send_message(color, Message::GameOver);
+                        } else if repetition {
+                            println!("Draw by repetition.");
+
+                            self.header.result =
+                                String::from(resolution::to_ptn_result(&Resolution::Draw));
 
                             logger::write_tmp_file(self);
                             logger::finalize_tmp_file();
@@ -301,7 +331,7 @@ impl Game {
                             self.send_message(color.flip(), Message::MoveRequest(state));
                         }
                     } else {
-                        println!("Bad move");
+                        println!("Bad move: {}", result.unwrap_err());
                         self.send_message(color, Message::MoveRequest(state));
                     }
                 }
@@ -316,19 +346,17 @@ impl Game {
                         if color == undo_color.flip() {
                             undo_requested = None;
 
-                            self.plies.pop();
+                            self.undo_ply();
                             print_game(self);
 
                             logger::write_tmp_file(self);
 
+                            // A game from TPS may not have started with White to move
+                            let state = self.to_state().unwrap();
                             self.send_message(undo_color, Message::UndoAccept);
                             self.send_message(
-                                if self.plies.len() % 2 == 0 {
-                                    Color::White
-                                } else {
-                                    Color::Black
-                                },
-                                Message::MoveRequest(self.to_state().unwrap()),
+                                rules::side_to_move(&state),
+                                Message::MoveRequest(state),
                             );
                         }
                     }
@@ -359,7 +387,7 @@ impl Game {
                         }
 
                         // If this is an early disconnect/end, end the game.  Otherwise, it's already over.
-                        if self.to_state().unwrap().check_resolution().is_none() {
+                        if resolution::game_result(&self.to_state().unwrap()).is_none() {
                             self.send_message(color.flip(), Message::GameOver);
                             self.send_message(color, Message::GameOver);
                         }
@@ -396,11 +424,26 @@ impl fmt::Display for Game {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}\r\n", self.header).ok();
 
-        for turn in 0..(self.plies.len() + 1) / 2 {
-            write!(f, "{:2}. {:7} ", turn + 1, self.plies[turn * 2].to_ptn()).ok();
+        // Replay the game so that flattening slides get their smash marker
+        let mut state = self.initial_state().ok();
+        let plies = self
+            .plies
+            .iter()
+            .map(|ply| match state {
+                Some(ref mut state) => {
+                    let ptn = ptn::ply_to_ptn(state, ply);
+                    state.execute_ply(Some(ply)).ok();
+                    ptn
+                }
+                None => ply.to_ptn(),
+            })
+            .collect::<Vec<_>>();
+
+        for turn in 0..(plies.len() + 1) / 2 {
+            write!(f, "{:2}. {:7} ", turn + 1, plies[turn * 2]).ok();
 
-            if turn * 2 + 1 < self.plies.len() {
-                write!(f, "{}", self.plies[turn * 2 + 1].to_ptn()).ok();
+            if turn * 2 + 1 < plies.len() {
+                write!(f, "{}", plies[turn * 2 + 1]).ok();
             }
 
             write!(f, "\r\n").ok();
@@ -435,8 +478,19 @@ fn print_game(game: &Game) {
         String::from("--")
     };
 
-    if state.check_resolution().is_some() {
-        println!("Final state:     {}\n", ptn);
+    if let Some(resolution) = resolution::game_result(&state) {
+        let resolution = resolution::apply_komi(&state, resolution, game.header.komi);
+
+        match resolution::flat_win_margin(&state, &resolution, game.header.komi) {
+            Some((color, margin)) => println!(
+                "Final state:     {}  ({} wins on flats by {}{})\n",
+                ptn,
+                rules::color_name(color),
+                margin / 2,
+                if margin % 2 == 1 { ".5" } else { "" },
+            ),
+            None => println!("Final state:     {}\n", ptn),
+        }
     } else if state.ply_count > 0 {
         println!(
             "Previous {}:   {}\n",
@@ -469,3 +523,32 @@ fn get_playtak_info(game: &Game) -> (usize, Color, Option<Vec<Ply>>) {
 
     (5, Color::White, None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_stands_a_flattened_wall_back_up() {
+        let mut game = Game::new();
+        let start = game.initial_state().unwrap();
+        let moves = ["a1", "e5", "Cc3", "Sc4", "c3+"];
+        let (before, _) = ptn::play_moves(&start, &moves[..4], true).unwrap();
+        let (_, plies) = ptn::play_moves(&start, &moves, true).unwrap();
+        game.plies = plies.clone();
+
+        assert!(game.undo_ply() == plies.last().cloned());
+
+        let state = game.to_state().unwrap();
+        assert!(rules::board_eq(&state, &before));
+        assert_eq!(state.ply_count, before.ply_count);
+    }
+
+    #[test]
+    fn undo_with_no_plies_does_nothing() {
+        let mut game = Game::new();
+
+        assert!(game.undo_ply().is_none());
+        assert!(game.plies.is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::analysis::Extrapolatable;
+use zero_sum::impls::tak::{Direction, Piece, Ply, State};
+use zero_sum::State as StateTrait;
+
+use super::rules;
+
+// All legal plies from the given state: generate_placements followed by generate_slides.  Every
+// candidate is kept only if rules::validate_ply accepts it.  It checks everything
+// State::execute_ply does, and each piece type against its own reserve, so anything returned here
+// is safe to play.
+pub fn generate_plies(state: &State) -> Vec<Ply> {
+    let mut plies = generate_placements(state);
+    plies.extend(generate_slides(state));
+    plies
+}
+
+// Placement candidates come from the search's extrapolation
+pub fn generate_placements(state: &State) -> Vec<Ply> {
+    legal_candidates(state, |ply| match *ply {
+        Ply::Place { .. } => true,
+        Ply::Slide { .. } => false,
+    })
+}
+
+// Every way of moving each stack the side to move controls, with the drops enumerated by
+// slide_drop_partitions.  Whatever stops a slide in its path is left for validate_ply to find.
+pub fn generate_slides(state: &State) -> Vec<Ply> {
+    let mut plies = Vec::new();
+
+    // Nothing can be moved until both sides have placed their first stone
+    if state.ply_count < 2 {
+        return plies;
+    }
+
+    let board_size = state.board.len();
+    let color = rules::side_to_move(state);
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            if rules::controller(state, x, y) != Some(color) {
+                continue;
+            }
+
+            // No more stones can be carried than the board is wide
+            let carry_limit = rules::stack_height(state, x, y).min(board_size);
+
+            for direction in rules::all_directions().iter() {
+                let squares = squares_to_edge(x, y, direction, board_size);
+
+                for grab in 1..=carry_limit {
+                    for drops in slide_drop_partitions(grab, squares) {
+                        let ply = Ply::Slide {
+                            x: x,
+                            y: y,
+                            direction: direction.clone(),
+                            drops: drops,
+                        };
+
+                        if rules::validate_ply(state, &ply).is_ok() {
+                            plies.push(ply);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    plies
+}
+
+// How many squares lie between the given one and the edge of the board in the direction
+fn squares_to_edge(x: usize, y: usize, direction: &Direction, board_size: usize) -> usize {
+    match *direction {
+        Direction::North => board_size - 1 - y,
+        Direction::East => board_size - 1 - x,
+        Direction::South => y,
+        Direction::West => x,
+    }
+}
+
+// The category is checked first so that only the wanted plies pay for validation
+fn legal_candidates<F>(state: &State, wanted: F) -> Vec<Ply>
+where
+    F: Fn(&Ply) -> bool,
+{
+    let candidates = if state.ply_count < 2 {
+        opening_placements(state)
+    } else {
+        state.extrapolate()
+    };
+
+    candidates
+        .into_iter()
+        .filter(|ply| wanted(ply) && rules::validate_ply(state, ply).is_ok())
+        .collect()
+}
+
+// The first two plies each place one of the opponent's flatstones, drawn from the opponent's
+// reserve, on any empty square.  Nothing else is legal, so these are built directly rather than
+// filtered out of extrapolate's plies.
+fn opening_placements(state: &State) -> Vec<Ply> {
+    let color = rules::opponent(state);
+
+    if rules::remaining_pieces(state, color).0 == 0 {
+        return Vec::new();
+    }
+
+    let board_size = state.board.len();
+    let mut plies = Vec::with_capacity(board_size * board_size);
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            if state.board[x][y].is_empty() {
+                plies.push(rules::place_ply(x, y, Piece::Flatstone(color)));
+            }
+        }
+    }
+
+    plies
+}
+
+// Counts the leaf nodes of the game tree to the given depth, for checking generate_plies against
+// published move counts
+pub fn perft(state: &State, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let plies = generate_plies(state);
+
+    if depth == 1 {
+        return plies.len() as u64;
+    }
+
+    plies
+        .iter()
+        .map(|ply| {
+            let mut next = state.clone();
+            next.execute_ply(Some(ply)).ok();
+            perft(&next, depth - 1)
+        })
+        .sum()
+}
+
+// Prints the perft count beneath each root ply, followed by the total
+pub fn perft_divide(state: &State, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut total = 0;
+
+    for ply in generate_plies(state) {
+        let mut next = state.clone();
+        next.execute_ply(Some(&ply)).ok();
+
+        let count = perft(&next, depth - 1);
+        println!("{:8} {}", ply.to_ptn(), count);
+
+        total += count;
+    }
+
+    println!("\nTotal: {}", total);
+
+    total
+}
+
+// Every way to drop a carry of grab stones over at most max_squares squares, as the drops of a
+// Ply::Slide.  Each is an ordered composition of grab into positive parts, generated lazily.
+pub fn slide_drop_partitions(grab: usize, max_squares: usize) -> DropPartitions {
+    DropPartitions {
+        grab: grab,
+        max_squares: max_squares,
+        mask: 0,
+    }
+}
+
+// Each bit of the mask marks a cut between two of the carried stones; the pieces between cuts are
+// the drops
+pub struct DropPartitions {
+    grab: usize,
+    max_squares: usize,
+    mask: u64,
+}
+
+impl Iterator for DropPartitions {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.grab == 0 || self.grab > 64 {
+            return None;
+        }
+
+        let cuts = self.grab - 1;
+
+        while self.mask < 1 << cuts {
+            let mask = self.mask;
+            self.mask += 1;
+
+            if mask.count_ones() as usize + 1 > self.max_squares {
+                continue;
+            }
+
+            let mut drops = Vec::with_capacity(mask.count_ones() as usize + 1);
+            let mut drop = 1;
+
+            for cut in 0..cuts {
+                if mask & 1 << cut != 0 {
+                    drops.push(drop);
+                    drop = 1;
+                } else {
+                    drop += 1;
+                }
+            }
+
+            drops.push(drop);
+            return Some(drops);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zero_sum::impls::tak::Color;
+
+    use crate::game::{ptn, tps};
+
+    #[test]
+    fn opening_position_has_one_placement_per_square() {
+        let state = rules::new_state(5).unwrap();
+
+        assert_eq!(generate_plies(&state).len(), 25);
+    }
+
+    #[test]
+    fn opening_plies_place_the_opponents_flatstones() {
+        let start = rules::new_state(5).unwrap();
+        let (second, _) = ptn::play_moves(&start, &["a1"], true).unwrap();
+
+        for (state, color) in vec![(start, Color::Black), (second, Color::White)] {
+            assert!(generate_slides(&state).is_empty());
+
+            let placements = generate_placements(&state);
+            assert_eq!(placements.len(), 25 - state.ply_count as usize);
+
+            for ply in placements {
+                match ply {
+                    Ply::Place {
+                        piece: Piece::Flatstone(piece_color),
+                        ..
+                    } => assert_eq!(piece_color, color),
+                    ply => panic!("{} isn't an opening placement", ply.to_ptn()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn counts_placements_and_slides_after_the_opening() {
+        let start = rules::new_state(5).unwrap();
+        let (state, _) = ptn::play_moves(&start, &["a1", "c3"], true).unwrap();
+
+        // A flatstone, standing stone, or capstone on each of 23 empty squares, and c3 in each
+        // direction
+        assert_eq!(generate_placements(&state).len(), 69);
+        assert_eq!(generate_slides(&state).len(), 4);
+        assert_eq!(generate_plies(&state).len(), 73);
+    }
+
+    #[test]
+    fn perft_from_the_5x5_start() {
+        let state = rules::new_state(5).unwrap();
+
+        assert_eq!(perft(&state, 1), 25);
+        assert_eq!(perft(&state, 2), 600);
+        // 600 positions, each with 69 placements and a slide of the white stone in every
+        // direction that stays on the board
+        assert_eq!(perft(&state, 3), 43_320);
+    }
+
+    #[test]
+    fn every_generated_ply_executes() {
+        let state = tps::from_tps("x5/x,2S,1,1C,x/x,1,21,2C,x/x,2,x3/x5 1 7").unwrap();
+        let plies = generate_plies(&state);
+
+        assert!(!plies.is_empty());
+        for ply in plies {
+            assert!(
+                state.clone().execute_ply(Some(&ply)).is_ok(),
+                "{} was generated but doesn't execute",
+                ply.to_ptn(),
+            );
+        }
+    }
+    #[test]
+    fn counts_the_compositions_of_a_carry() {
+        let mut partitions = slide_drop_partitions(3, 3).collect::<Vec<_>>();
+        partitions.sort();
+
+        assert_eq!(
+            partitions,
+            vec![vec![1, 1, 1], vec![1, 2], vec![2, 1], vec![3]],
+        );
+        assert_eq!(slide_drop_partitions(3, 2).count(), 3);
+        assert_eq!(slide_drop_partitions(3, 0).count(), 0);
+    }
+
+    #[test]
+    fn generates_the_same_slides_as_the_extrapolation() {
+        let state = tps::from_tps("x5/x,2S,1,1C,x/x,1,21,2C,x/x,2,x3/x5 1 7").unwrap();
+        let extrapolated = legal_candidates(&state, |ply| match *ply {
+            Ply::Place { .. } => false,
+            Ply::Slide { .. } => true,
+        });
+        let generated = generate_slides(&state);
+
+        assert_eq!(generated.len(), extrapolated.len());
+        for ply in extrapolated {
+            assert!(
+                generated.contains(&ply),
+                "{} wasn't generated",
+                ply.to_ptn()
+            );
+        }
+    }
+}
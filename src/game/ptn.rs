@@ -0,0 +1,446 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use zero_sum::impls::tak::{Color, Direction, Piece, Ply, State};
+use zero_sum::State as StateTrait;
+
+use super::rules::{self, PlyEffect};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PtnError {
+    Empty,
+    UnknownPieceChar(char),
+    PieceOnSlide(char),
+    BadCoordinate(String),
+    MissingDirection,
+    // A slide has to pick up at least one stone, and leave at least one on every square it crosses
+    ZeroGrab,
+    ZeroDrop,
+    DropSumMismatch { grab: u8, drops: u8 },
+    // The drops add up to more than a u8 can count
+    DropSumOverflow { grab: u8 },
+    TrailingGarbage(String),
+}
+
+impl fmt::Display for PtnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PtnError::Empty => write!(f, "no move given"),
+            PtnError::UnknownPieceChar(c) => write!(f, "unknown piece '{}'", c),
+            PtnError::PieceOnSlide(c) => write!(f, "a slide can't name a piece ('{}')", c),
+            PtnError::BadCoordinate(ref square) => write!(f, "bad square \"{}\"", square),
+            PtnError::MissingDirection => write!(f, "a stone count must be followed by a slide"),
+            PtnError::ZeroGrab => write!(f, "a slide must pick up at least one stone"),
+            PtnError::ZeroDrop => write!(f, "a slide must drop at least one stone on each square"),
+            PtnError::DropSumMismatch { grab, drops } => write!(
+                f,
+                "drops add up to {}, but {} stones were picked up",
+                drops, grab,
+            ),
+            PtnError::DropSumOverflow { grab } => write!(
+                f,
+                "drops add up to far more than the {} stones picked up",
+                grab,
+            ),
+            PtnError::TrailingGarbage(ref rest) => write!(f, "unexpected \"{}\"", rest),
+        }
+    }
+}
+
+impl Error for PtnError {}
+
+// Parses a single move in PTN, reporting what was wrong with it.  Ply::from_ptn remains the
+// lenient version for callers that only care whether the move parsed.
+pub fn parse_ptn(ptn: &str, color: Color) -> Result<Ply, PtnError> {
+    let mut chars = ptn.trim().chars().peekable();
+
+    if chars.peek().is_none() {
+        return Err(PtnError::Empty);
+    }
+
+    let grab = match chars.peek().and_then(|c| c.to_digit(10)) {
+        Some(grab) => {
+            chars.next();
+            Some(grab as u8)
+        }
+        None => None,
+    };
+
+    let piece_char = match chars.peek() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            chars.next();
+            Some(c)
+        }
+        _ => None,
+    };
+
+    let (x, y) = parse_square(&mut chars)?;
+
+    let direction = chars.peek().and_then(|&c| direction_from_glyph(c));
+
+    let ply = if let Some(direction) = direction {
+        chars.next();
+
+        if let Some(c) = piece_char {
+            return Err(PtnError::PieceOnSlide(c));
+        }
+
+        let grab = grab.unwrap_or(1);
+        if grab == 0 {
+            return Err(PtnError::ZeroGrab);
+        }
+
+        let mut drops = Vec::new();
+        while let Some(drop) = chars.peek().and_then(|c| c.to_digit(10)) {
+            chars.next();
+            drops.push(drop as u8);
+        }
+
+        if drops.is_empty() {
+            drops.push(grab);
+        } else if drops.contains(&0) {
+            return Err(PtnError::ZeroDrop);
+        }
+
+        let drop_sum = drops
+            .iter()
+            .try_fold(0u8, |sum, &drop| sum.checked_add(drop))
+            .ok_or(PtnError::DropSumOverflow { grab: grab })?;
+        if drop_sum != grab {
+            return Err(PtnError::DropSumMismatch {
+                grab: grab,
+                drops: drop_sum,
+            });
+        }
+
+        Ply::Slide {
+            x: x,
+            y: y,
+            direction: direction,
+            drops: drops,
+        }
+    } else {
+        if grab.is_some() {
+            return Err(PtnError::MissingDirection);
+        }
+
+        let piece = match piece_char {
+            None | Some('F') => Piece::Flatstone(color),
+            Some('S') => Piece::StandingStone(color),
+            Some('C') => Piece::Capstone(color),
+            Some(c) => return Err(PtnError::UnknownPieceChar(c)),
+        };
+
+        Ply::Place {
+            x: x,
+            y: y,
+            piece: piece,
+        }
+    };
+
+    // Smash markers and annotations carry no information about the move itself
+    while let Some(&c) = chars.peek() {
+        if c == '*' || c == '\'' || c == '?' || c == '!' {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+
+    let rest = chars.collect::<String>();
+    if !rest.is_empty() {
+        return Err(PtnError::TrailingGarbage(rest));
+    }
+
+    Ok(ply)
+}
+
+// Parses and plays a move for whoever is to move, returning the resulting state.  During the
+// opening the stone placed belongs to the opponent, so the parsing color is swapped.
+pub fn apply_ptn_move(state: &State, ptn: &str) -> Result<State, String> {
+    let color = if state.ply_count < 2 {
+        rules::opponent(state)
+    } else {
+        rules::side_to_move(state)
+    };
+
+    let ply = match parse_ptn(ptn, color) {
+        Ok(ply) => ply,
+        Err(error) => return Err(format!("Invalid move \"{}\": {}.", ptn, error)),
+    };
+
+    if let Err(error) = rules::validate_ply(state, &ply) {
+        return Err(format!("Illegal move \"{}\": {}", ptn, error));
+    }
+
+    let mut next = state.clone();
+    next.execute_ply(Some(&ply))?;
+
+    Ok(next)
+}
+
+// Plays a list of PTN moves from the start, checking that each is legal, and returns the final
+// state and the plies played.  With swap_opening, the first two moves place the opponent's stones,
+// as in a standard game, so callers don't have to color them; turn it off for a custom start, such
+// as a TPS position.  An error carries the index of the move that failed.
+pub fn play_moves(
+    start: &State,
+    moves: &[&str],
+    swap_opening: bool,
+) -> Result<(State, Vec<Ply>), (usize, String)> {
+    let mut state = start.clone();
+    let mut plies = Vec::with_capacity(moves.len());
+
+    for (index, ptn) in moves.iter().enumerate() {
+        let color = if swap_opening && state.ply_count < 2 {
+            rules::opponent(&state)
+        } else {
+            rules::side_to_move(&state)
+        };
+
+        let ply = parse_ptn(ptn, color).map_err(|error| (index, error.to_string()))?;
+        rules::execute_validated(&mut state, &ply).map_err(|error| (index, error))?;
+        plies.push(ply);
+    }
+
+    Ok((state, plies))
+}
+
+// Writes the ply as it would be played from the given state.  Unlike Ply::to_ptn, which can't see
+// the board, it marks a slide that flattens a standing stone with "*".  A count of one stone, and a
+// single drop of the whole carry, are left implied.
+pub fn ply_to_ptn(state: &State, ply: &Ply) -> String {
+    let mut ptn = match *ply {
+        Ply::Place { x, y, ref piece } => {
+            let prefix = match *piece {
+                Piece::Flatstone(_) => "",
+                Piece::StandingStone(_) => "S",
+                Piece::Capstone(_) => "C",
+            };
+
+            format!("{}{}", prefix, square_name(x, y))
+        }
+        Ply::Slide {
+            x,
+            y,
+            ref direction,
+            ref drops,
+        } => {
+            let grab = drops.iter().map(|&drop| drop as u32).sum::<u32>();
+            let mut ptn = String::new();
+
+            if grab > 1 {
+                ptn.push_str(&grab.to_string());
+            }
+
+            ptn.push_str(&square_name(x, y));
+            ptn.push(direction_glyph(direction));
+
+            if drops.len() > 1 {
+                for drop in drops {
+                    ptn.push_str(&drop.to_string());
+                }
+            }
+
+            ptn
+        }
+    };
+
+    let flattens = rules::ply_effects(state, ply)
+        .iter()
+        .any(|effect| match *effect {
+            PlyEffect::Flattened { .. } => true,
+        });
+
+    if flattens {
+        ptn.push('*');
+    }
+
+    ptn
+}
+
+pub fn direction_from_glyph(glyph: char) -> Option<Direction> {
+    match glyph {
+        '+' => Some(Direction::North),
+        '>' => Some(Direction::East),
+        '-' => Some(Direction::South),
+        '<' => Some(Direction::West),
+        _ => None,
+    }
+}
+
+// Accepts compass names and up/down/left/right, in any case, for casual move entry.  PTN is
+// still always written with direction_glyph.
+pub fn direction_from_name(name: &str) -> Option<Direction> {
+    match name.to_lowercase().as_str() {
+        "north" | "up" => Some(Direction::North),
+        "east" | "right" => Some(Direction::East),
+        "south" | "down" => Some(Direction::South),
+        "west" | "left" => Some(Direction::West),
+        _ => None,
+    }
+}
+
+pub fn direction_glyph(direction: &Direction) -> char {
+    match *direction {
+        Direction::North => '+',
+        Direction::East => '>',
+        Direction::South => '-',
+        Direction::West => '<',
+    }
+}
+
+fn square_name(x: usize, y: usize) -> String {
+    format!("{}{}", (b'a' + x as u8) as char, y + 1)
+}
+
+fn parse_square(chars: &mut Peekable<Chars>) -> Result<(usize, usize), PtnError> {
+    let file = chars.next();
+    let rank = chars.next();
+
+    match (file, rank) {
+        (Some(file @ 'a'..='h'), Some(rank @ '1'..='8')) => {
+            Ok(((file as u8 - b'a') as usize, (rank as u8 - b'1') as usize))
+        }
+        _ => Err(PtnError::BadCoordinate(
+            file.into_iter().chain(rank.into_iter()).collect(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_drops_that_dont_add_up() {
+        assert_eq!(
+            parse_ptn("3a1>11", Color::White).err(),
+            Some(PtnError::DropSumMismatch { grab: 3, drops: 2 }),
+        );
+        assert_eq!(
+            parse_ptn("1a1>99999", Color::White).err(),
+            Some(PtnError::DropSumMismatch { grab: 1, drops: 45 }),
+        );
+    }
+
+    #[test]
+    fn reports_slides_of_no_stones() {
+        assert_eq!(
+            parse_ptn("0a1>", Color::White).err(),
+            Some(PtnError::ZeroGrab)
+        );
+        assert_eq!(
+            parse_ptn("2a1>20", Color::White).err(),
+            Some(PtnError::ZeroDrop)
+        );
+        assert_eq!(
+            parse_ptn("2a1>02", Color::White).err(),
+            Some(PtnError::ZeroDrop)
+        );
+    }
+
+    #[test]
+    fn reports_drops_too_large_to_count() {
+        let ptn = format!("1a1>{}", "9".repeat(40));
+
+        assert_eq!(
+            parse_ptn(&ptn, Color::White).err(),
+            Some(PtnError::DropSumOverflow { grab: 1 }),
+        );
+    }
+    fn assert_round_trips(ply: Ply, color: Color) {
+        assert_eq!(parse_ptn(&ply.to_ptn(), color), Ok(ply));
+    }
+
+    #[test]
+    fn placements_round_trip() {
+        for &color in &[Color::White, Color::Black] {
+            let pieces = vec![
+                Piece::Flatstone(color),
+                Piece::StandingStone(color),
+                Piece::Capstone(color),
+            ];
+
+            for piece in pieces {
+                assert_round_trips(
+                    Ply::Place {
+                        x: 1,
+                        y: 3,
+                        piece: piece,
+                    },
+                    color,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn slides_round_trip() {
+        let slides = vec![
+            (0, 0, Direction::North, vec![1]),
+            (2, 2, Direction::South, vec![3]),
+            (0, 4, Direction::East, vec![1, 2]),
+            (4, 4, Direction::West, vec![2, 1, 1]),
+        ];
+
+        for (x, y, direction, drops) in slides {
+            assert_round_trips(
+                Ply::Slide {
+                    x: x,
+                    y: y,
+                    direction: direction,
+                    drops: drops,
+                },
+                Color::White,
+            );
+        }
+    }
+    #[test]
+    fn the_opening_places_the_opponents_stones() {
+        let start = rules::new_state(5).unwrap();
+
+        let (state, _) = play_moves(&start, &["a1", "e5"], true).unwrap();
+        assert_eq!(state.board[0][0], vec![Piece::Flatstone(Color::Black)]);
+        assert_eq!(state.board[4][4], vec![Piece::Flatstone(Color::White)]);
+
+        // Without the swap, White's own stone on a1 breaks the opening rule
+        assert_eq!(play_moves(&start, &["a1"], false).unwrap_err().0, 0);
+    }
+
+    #[test]
+    fn writes_plies_in_standard_notation() {
+        let start = rules::new_state(5).unwrap();
+        let moves = [
+            "a1", "e5", "c3", "Sb3", "Cc4", "b2", "c4-", "b1", "2c3>11", "Se4", "e3+*",
+        ];
+        let (_, plies) = play_moves(&start, &moves, true).unwrap();
+        let mut state = start;
+
+        for (ptn, ply) in moves.iter().zip(plies.iter()) {
+            assert_eq!(ply_to_ptn(&state, ply), *ptn);
+            state.execute_ply(Some(ply)).unwrap();
+        }
+    }
+}
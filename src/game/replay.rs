@@ -0,0 +1,142 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::impls::tak::{Ply, State};
+use zero_sum::State as StateTrait;
+
+use super::{logger, rules, Game};
+
+// Keep a copy of every this-many-th state, so that stepping backward replays at most this many
+// plies while memory grows only with the game length over this
+const CHECKPOINT_INTERVAL: usize = 8;
+
+// Steps through a finished game's plies in either direction, e.g. for a replay viewer
+pub struct Replay {
+    plies: Vec<Ply>,
+    // checkpoints[i] is the state after i * CHECKPOINT_INTERVAL plies
+    checkpoints: Vec<State>,
+    cursor: usize,
+    current: State,
+}
+
+impl Replay {
+    // Checks every ply up front, reporting the first illegal one
+    pub fn new(initial: State, plies: Vec<Ply>) -> Result<Replay, String> {
+        let mut checkpoints = vec![initial.clone()];
+        let mut state = initial.clone();
+
+        for (index, ply) in plies.iter().enumerate() {
+            if let Err(error) = rules::execute_validated(&mut state, ply) {
+                return Err(format!(
+                    "Ply {} ({}) is illegal: {}",
+                    index + 1,
+                    ply.to_ptn(),
+                    error
+                ));
+            }
+
+            if (index + 1) % CHECKPOINT_INTERVAL == 0 {
+                checkpoints.push(state.clone());
+            }
+        }
+
+        Ok(Replay {
+            plies: plies,
+            checkpoints: checkpoints,
+            cursor: 0,
+            current: initial,
+        })
+    }
+
+    pub fn from_ptn(data: &str) -> Result<Replay, String> {
+        let (header, plies) = logger::parse_ptn(data)?;
+        let initial = Game::into_state(header, Vec::new())?;
+        Replay::new(initial, plies)
+    }
+
+    pub fn len(&self) -> usize {
+        self.plies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plies.is_empty()
+    }
+
+    // The number of plies played to reach the current state
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn current_state(&self) -> &State {
+        &self.current
+    }
+
+    // The ply that led to the current state
+    pub fn last_ply(&self) -> Option<&Ply> {
+        if self.cursor > 0 {
+            self.plies.get(self.cursor - 1)
+        } else {
+            None
+        }
+    }
+
+    // Moves to the state after n plies, clamped to the end of the game
+    pub fn goto_ply(&mut self, n: usize) -> &State {
+        let n = n.min(self.plies.len());
+
+        // Replay from the nearest checkpoint at or before n, unless the current state is closer
+        if n < self.cursor || n - self.cursor > n % CHECKPOINT_INTERVAL {
+            let checkpoint = n / CHECKPOINT_INTERVAL;
+            self.current = self.checkpoints[checkpoint].clone();
+            self.cursor = checkpoint * CHECKPOINT_INTERVAL;
+        }
+
+        while self.cursor < n {
+            // Every ply was checked in new
+            self.current
+                .execute_ply(Some(&self.plies[self.cursor]))
+                .ok();
+            self.cursor += 1;
+        }
+
+        &self.current
+    }
+
+    // Returns false at the end of the game
+    pub fn step_forward(&mut self) -> bool {
+        if self.cursor < self.plies.len() {
+            let n = self.cursor + 1;
+            self.goto_ply(n);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns false at the start of the game
+    pub fn step_back(&mut self) -> bool {
+        if self.cursor > 0 {
+            let n = self.cursor - 1;
+            self.goto_ply(n);
+            true
+        } else {
+            false
+        }
+    }
+}
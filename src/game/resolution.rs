@@ -0,0 +1,770 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::impls::tak::{Color, Direction, Piece, Ply, Resolution, State};
+
+use zero_sum::State as StateTrait;
+
+use super::{movegen, rules};
+
+// The number of stacks topped by each color's flatstones, (White, Black)
+pub fn flat_counts(state: &State) -> (u32, u32) {
+    let mut counts = (0, 0);
+
+    for stack in state.board.iter().flat_map(|column| column.iter()) {
+        match stack.last() {
+            Some(&Piece::Flatstone(Color::White)) => counts.0 += 1,
+            Some(&Piece::Flatstone(Color::Black)) => counts.1 += 1,
+            _ => (),
+        }
+    }
+
+    counts
+}
+
+// Counts only stacks whose top piece is one of the color's flatstones; standing stones and
+// capstones don't score, matching how check_resolution decides a flat win
+pub fn flat_count(state: &State, color: Color) -> u32 {
+    let (white, black) = flat_counts(state);
+
+    match color {
+        Color::White => white,
+        Color::Black => black,
+    }
+}
+
+// White's flat count minus Black's
+pub fn flat_differential(state: &State) -> i32 {
+    let (white, black) = flat_counts(state);
+    white as i32 - black as i32
+}
+
+// check_resolution, extended to end the game when the side to move has no legal ply.  The rules
+// shouldn't allow that to happen, but if it does the game is scored on flats as if the board had
+// filled, rather than leaving the game loop waiting on a move that can't be made.  Finding out
+// costs a generate_plies, so a search that generates the node's plies anyway should call
+// check_resolution itself and use no_legal_ply_result only once that list comes back empty.
+pub fn game_result(state: &State) -> Option<Resolution> {
+    if let Some(resolution) = state.check_resolution() {
+        return Some(resolution);
+    }
+
+    if movegen::generate_plies(state).is_empty() {
+        Some(no_legal_ply_result(state))
+    } else {
+        None
+    }
+}
+
+// The result of a game in which the side to move has no legal ply
+pub fn no_legal_ply_result(state: &State) -> Resolution {
+    let (white, black) = flat_counts(state);
+
+    if white > black {
+        Resolution::Flat(Color::White)
+    } else if black > white {
+        Resolution::Flat(Color::Black)
+    } else {
+        Resolution::Draw
+    }
+}
+
+// Whether playing the ply completes a road for the player making it
+pub fn would_win_by_road(state: &State, ply: &Ply) -> bool {
+    let mover = rules::side_to_move(state);
+    let mut next = state.clone();
+
+    if rules::execute_validated(&mut next, ply).is_err() {
+        return false;
+    }
+
+    match next.check_resolution() {
+        Some(Resolution::Road(color)) => color == mover,
+        _ => false,
+    }
+}
+
+// Whether the color could complete a road with its next ply, i.e. whether it has "Tak".  This
+// asks what the color could do if it were its turn, whoever is actually to move, so a threat the
+// side to move has just made can be checked too.  Nothing can be threatened during the opening,
+// when each player places the other's stone.
+pub fn is_tak(state: &State, color: Color) -> bool {
+    if state.ply_count < 2 {
+        return false;
+    }
+
+    let mut hypothetical = state.clone();
+    if rules::side_to_move(state) != color {
+        hypothetical.ply_count += 1;
+    }
+
+    completes_road(&hypothetical, &movegen::generate_plies(&hypothetical))
+}
+
+// Whether any of the plies, which must be the side to move's, would complete its road.  is_tak
+// for a caller that has already generated the side to move's plies.  A placement can only join the
+// road groups beside it, so the groups are found once for every placement, and only slides are
+// played out.
+pub fn completes_road(state: &State, plies: &[Ply]) -> bool {
+    let mover = rules::side_to_move(state);
+    let mut edges = None;
+
+    plies.iter().any(|ply| match *ply {
+        Ply::Place { x, y, ref piece } => {
+            let road_piece = match *piece {
+                Piece::Flatstone(owner) | Piece::Capstone(owner) => owner == mover,
+                Piece::StandingStone(_) => false,
+            };
+            if !road_piece {
+                return false;
+            }
+
+            let edges = edges.get_or_insert_with(|| group_edges(state, mover));
+            let mut touched = square_edges(x, y, state.board.len());
+            for direction in rules::all_directions().iter() {
+                if let Some((next_x, next_y)) = neighbor(x, y, direction, state.board.len()) {
+                    touched |= edges[next_x][next_y];
+                }
+            }
+
+            touched & (WEST | EAST) == WEST | EAST || touched & (SOUTH | NORTH) == SOUTH | NORTH
+        }
+        Ply::Slide { .. } => would_win_by_road(state, ply),
+    })
+}
+
+const WEST: u8 = 1;
+const EAST: u8 = 2;
+const SOUTH: u8 = 4;
+const NORTH: u8 = 8;
+
+// The board edges the square lies on
+fn square_edges(x: usize, y: usize, board_size: usize) -> u8 {
+    let mut edges = 0;
+    if x == 0 {
+        edges |= WEST;
+    }
+    if x == board_size - 1 {
+        edges |= EAST;
+    }
+    if y == 0 {
+        edges |= SOUTH;
+    }
+    if y == board_size - 1 {
+        edges |= NORTH;
+    }
+    edges
+}
+
+// For each of the color's road squares, the edges its road group reaches, indexed [x][y]
+fn group_edges(state: &State, color: Color) -> Vec<Vec<u8>> {
+    let board_size = state.board.len();
+    let mut edges = vec![vec![0; board_size]; board_size];
+
+    for group in road_groups(state, color) {
+        let reached = group.iter().fold(0, |reached, &(x, y)| {
+            reached | square_edges(x, y, board_size)
+        });
+
+        for &(x, y) in group.iter() {
+            edges[x][y] = reached;
+        }
+    }
+
+    edges
+}
+
+// The square beside the given one in the direction, if it's on the board
+fn neighbor(
+    x: usize,
+    y: usize,
+    direction: &Direction,
+    board_size: usize,
+) -> Option<(usize, usize)> {
+    let (dx, dy) = rules::direction_offset(direction);
+    let (next_x, next_y) = (x as isize + dx, y as isize + dy);
+
+    if next_x < 0 || next_y < 0 || next_x >= board_size as isize || next_y >= board_size as isize {
+        None
+    } else {
+        Some((next_x as usize, next_y as usize))
+    }
+}
+
+// White's scored flat margin in half-flats, i.e. the flat differential less Black's komi
+pub fn flat_margin(state: &State, komi: i32) -> i32 {
+    2 * flat_differential(state) - komi
+}
+
+// The winner of a flat win and the margin they won by, in half-flats with komi included.  None for
+// roads and draws.
+pub fn flat_win_margin(state: &State, resolution: &Resolution, komi: i32) -> Option<(Color, u32)> {
+    match *resolution {
+        Resolution::Flat(color) => {
+            let margin = flat_margin(state, komi);
+
+            match color {
+                Color::White if margin > 0 => Some((color, margin as u32)),
+                Color::Black if margin < 0 => Some((color, -margin as u32)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Rescores a game that ended on flats, giving Black komi half-flats on top of its flat count.
+// Road wins are returned unchanged.
+pub fn apply_komi(state: &State, resolution: Resolution, komi: i32) -> Resolution {
+    match resolution {
+        Resolution::Road(_) => resolution,
+        Resolution::Flat(_) | Resolution::Draw => flat_result(state, komi),
+    }
+}
+
+// Who would win on flats, with komi, if the game ended now.  The game needn't be over, so this
+// works as a "who's ahead" indicator at any point.
+pub fn flat_result(state: &State, komi: i32) -> Resolution {
+    let margin = flat_margin(state, komi);
+
+    if margin > 0 {
+        Resolution::Flat(Color::White)
+    } else if margin < 0 {
+        Resolution::Flat(Color::Black)
+    } else {
+        Resolution::Draw
+    }
+}
+
+// None for a draw
+pub fn winner(resolution: &Resolution) -> Option<Color> {
+    match *resolution {
+        Resolution::Road(color) | Resolution::Flat(color) => Some(color),
+        Resolution::Draw => None,
+    }
+}
+
+pub fn is_draw(resolution: &Resolution) -> bool {
+    winner(resolution).is_none()
+}
+
+pub fn to_ptn_result(resolution: &Resolution) -> &'static str {
+    match *resolution {
+        Resolution::Road(Color::White) => "R-0",
+        Resolution::Road(Color::Black) => "0-R",
+        Resolution::Flat(Color::White) => "F-0",
+        Resolution::Flat(Color::Black) => "0-F",
+        Resolution::Draw => "1/2-1/2",
+    }
+}
+
+// to_ptn_result, with "*" for a game that's still in progress
+pub fn to_ptn_result_or_ongoing(resolution: Option<&Resolution>) -> &'static str {
+    resolution.map_or("*", to_ptn_result)
+}
+
+// The reverse of to_ptn_result_or_ongoing, so "*" gives None.  Results a Resolution can't hold,
+// like a win by time or resignation, are errors.
+pub fn from_ptn_result(result: &str) -> Result<Option<Resolution>, String> {
+    match result.trim() {
+        "R-0" => Ok(Some(Resolution::Road(Color::White))),
+        "0-R" => Ok(Some(Resolution::Road(Color::Black))),
+        "F-0" => Ok(Some(Resolution::Flat(Color::White))),
+        "0-F" => Ok(Some(Resolution::Flat(Color::Black))),
+        "1/2-1/2" => Ok(Some(Resolution::Draw)),
+        "*" => Ok(None),
+        _ => Err(format!("Unsupported result: \"{}\".", result)),
+    }
+}
+
+// How a game ended, including the outcomes the board can't show.  check_resolution and game_result
+// only ever decide Board; the others are recorded by whoever runs the game.
+#[derive(Clone, Debug)]
+pub enum GameResult {
+    Board(Resolution),
+    // The color that resigned
+    Resignation(Color),
+    AgreedDraw,
+}
+
+impl GameResult {
+    // None for a draw
+    pub fn winner(&self) -> Option<Color> {
+        match *self {
+            GameResult::Board(ref resolution) => winner(resolution),
+            GameResult::Resignation(color) => Some(color.flip()),
+            GameResult::AgreedDraw => None,
+        }
+    }
+
+    pub fn to_ptn_result(&self) -> &'static str {
+        match *self {
+            GameResult::Board(ref resolution) => to_ptn_result(resolution),
+            GameResult::Resignation(Color::White) => "0-1",
+            GameResult::Resignation(Color::Black) => "1-0",
+            GameResult::AgreedDraw => "1/2-1/2",
+        }
+    }
+}
+
+// Whether the square's top piece counts toward a road for the color
+pub fn is_road_square(state: &State, x: usize, y: usize, color: Color) -> bool {
+    match rules::top_piece(state, x, y) {
+        Some(&Piece::Flatstone(owner)) | Some(&Piece::Capstone(owner)) => owner == color,
+        _ => false,
+    }
+}
+
+// Whether the square's top piece keeps its owner's opponent from running a road through it for
+// good.  The opponent can't stack onto a capstone or a standing stone, so it can only take the
+// square back by moving the stack off, or, for a standing stone, by flattening it with a capstone.
+// A flatstone blocks only until the opponent captures it by stacking on top, so it doesn't count.
+pub fn blocks_opponent_road(state: &State, x: usize, y: usize) -> bool {
+    match rules::top_piece(state, x, y) {
+        Some(piece) => rules::is_blocking(piece),
+        None => false,
+    }
+}
+
+// The orthogonally connected groups of the color's road squares
+pub fn road_groups(state: &State, color: Color) -> Vec<Vec<(usize, usize)>> {
+    let board_size = state.board.len();
+    let mut visited = vec![vec![false; board_size]; board_size];
+    let mut groups = Vec::new();
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            if visited[x][y] || !is_road_square(state, x, y, color) {
+                continue;
+            }
+
+            let mut group = Vec::new();
+            let mut frontier = vec![(x, y)];
+            visited[x][y] = true;
+
+            while let Some((group_x, group_y)) = frontier.pop() {
+                group.push((group_x, group_y));
+
+                for direction in rules::all_directions().iter() {
+                    let (next_x, next_y) = match neighbor(group_x, group_y, direction, board_size) {
+                        Some(square) => square,
+                        None => continue,
+                    };
+
+                    if !visited[next_x][next_y] && is_road_square(state, next_x, next_y, color) {
+                        visited[next_x][next_y] = true;
+                        frontier.push((next_x, next_y));
+                    }
+                }
+            }
+
+            groups.push(group);
+        }
+    }
+
+    groups
+}
+
+// Whether the group touches both west and east edges, or both south and north edges
+pub fn is_road(group: &[(usize, usize)], board_size: usize) -> bool {
+    let touches = |edge: &dyn Fn(&(usize, usize)) -> bool| group.iter().any(|square| edge(square));
+
+    (touches(&|&(x, _)| x == 0) && touches(&|&(x, _)| x == board_size - 1))
+        || (touches(&|&(_, y)| y == 0) && touches(&|&(_, y)| y == board_size - 1))
+}
+
+// The road that won the game, if any, as the squares of the connected group that forms it.  When
+// both colors have a road, the player who made the last ply wins, as in check_resolution.
+pub fn winning_road(state: &State) -> Option<(Color, Vec<(usize, usize)>)> {
+    let board_size = state.board.len();
+    let find_road = |color| {
+        road_groups(state, color)
+            .into_iter()
+            .find(|group| is_road(group, board_size))
+    };
+
+    match (find_road(Color::White), find_road(Color::Black)) {
+        (Some(white), Some(black)) => {
+            if state.ply_count % 2 == 1 {
+                Some((Color::White, white))
+            } else {
+                Some((Color::Black, black))
+            }
+        }
+        (Some(white), None) => Some((Color::White, white)),
+        (None, Some(black)) => Some((Color::Black, black)),
+        (None, None) => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RoadProgress {
+    pub largest_group: u32,
+    // Some group connects the south and north edges
+    pub spans_vertical: bool,
+    // Some group connects the west and east edges
+    pub spans_horizontal: bool,
+}
+
+// How far along the color is toward a road, from its road groups alone
+pub fn road_progress(state: &State, color: Color) -> RoadProgress {
+    let board_size = state.board.len();
+    let mut progress = RoadProgress::default();
+
+    for group in road_groups(state, color) {
+        let touches =
+            |edge: &dyn Fn(&(usize, usize)) -> bool| group.iter().any(|square| edge(square));
+
+        progress.largest_group = progress.largest_group.max(group.len() as u32);
+        progress.spans_vertical |=
+            touches(&|&(_, y)| y == 0) && touches(&|&(_, y)| y == board_size - 1);
+        progress.spans_horizontal |=
+            touches(&|&(x, _)| x == 0) && touches(&|&(x, _)| x == board_size - 1);
+    }
+
+    progress
+}
+
+// The squares a color's road could still grow into, indexed [x][y]: its own road squares, and
+// every empty square orthogonally reachable from them through other empty squares or its road
+// squares.  Anything else stops the spread.
+pub fn influence(state: &State, color: Color) -> Vec<Vec<bool>> {
+    flood_fill(
+        state,
+        |x, y| is_road_square(state, x, y, color),
+        |x, y| state.board[x][y].is_empty(),
+    )
+}
+
+// The squares a color could ever use in a road, indexed [x][y]: every square reachable from the
+// stacks it controls through anything but the opponent's standing stones and capstones, which it
+// can't take over.  The rest are dead for its roads.
+pub fn reachable_for_road(state: &State, color: Color) -> Vec<Vec<bool>> {
+    let controlled = rules::controlled_squares(state, color);
+
+    flood_fill(
+        state,
+        |x, y| controlled[x][y],
+        |x, y| match rules::top_piece(state, x, y) {
+            Some(&Piece::StandingStone(owner)) | Some(&Piece::Capstone(owner)) => owner == color,
+            _ => true,
+        },
+    )
+}
+
+// Every seed square, and every passable square orthogonally connected to one through passable
+// squares
+fn flood_fill<S, P>(state: &State, seed: S, passable: P) -> Vec<Vec<bool>>
+where
+    S: Fn(usize, usize) -> bool,
+    P: Fn(usize, usize) -> bool,
+{
+    let board_size = state.board.len();
+    let mut filled = vec![vec![false; board_size]; board_size];
+    let mut frontier = Vec::new();
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            if seed(x, y) {
+                filled[x][y] = true;
+                frontier.push((x, y));
+            }
+        }
+    }
+
+    while let Some((x, y)) = frontier.pop() {
+        for direction in rules::all_directions().iter() {
+            let (next_x, next_y) = match neighbor(x, y, direction, board_size) {
+                Some(square) => square,
+                None => continue,
+            };
+
+            if !filled[next_x][next_y] && passable(next_x, next_y) {
+                filled[next_x][next_y] = true;
+                frontier.push((next_x, next_y));
+            }
+        }
+    }
+
+    filled
+}
+
+// Positions estimated to have at most this many plies left are small enough to solve outright
+pub const ENDGAME_PLIES: usize = 4;
+
+// The result of the game under perfect play from both sides, found by searching every line to the
+// end of the game.  None if the position isn't close enough to the end to try, or if some line
+// runs past ENDGAME_PLIES without a result and nothing better for the side to move was found.
+pub fn solve_endgame(state: &State) -> Option<Resolution> {
+    if rules::estimate_remaining_plies(state) > ENDGAME_PLIES {
+        return None;
+    }
+
+    solve(state, ENDGAME_PLIES)
+}
+
+fn solve(state: &State, plies_left: usize) -> Option<Resolution> {
+    if let Some(resolution) = state.check_resolution() {
+        return Some(resolution);
+    }
+
+    if plies_left == 0 {
+        return None;
+    }
+
+    let plies = movegen::generate_plies(state);
+    if plies.is_empty() {
+        return Some(no_legal_ply_result(state));
+    }
+
+    let mover = rules::side_to_move(state);
+    let value = |resolution: &Resolution| match winner(resolution) {
+        Some(color) if color == mover => 1,
+        Some(_) => -1,
+        None => 0,
+    };
+
+    let mut best: Option<Resolution> = None;
+    let mut unsolved = false;
+
+    for ply in plies {
+        let mut next = state.clone();
+        if next.execute_ply(Some(&ply)).is_err() {
+            continue;
+        }
+
+        match solve(&next, plies_left - 1) {
+            Some(resolution) => {
+                if best
+                    .as_ref()
+                    .map_or(true, |best| value(&resolution) > value(best))
+                {
+                    // Nothing beats a win, so the other plies needn't be searched
+                    if value(&resolution) == 1 {
+                        return Some(resolution);
+                    }
+                    best = Some(resolution);
+                }
+            }
+            None => unsolved = true,
+        }
+    }
+
+    // An unsolved line might have been better than anything found
+    if unsolved {
+        None
+    } else {
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ptn, self_play, tps};
+
+    // White leads 10 flats to 9, with no road
+    fn ten_to_nine() -> State {
+        tps::from_tps("1,2,1,2,1/2,1,2,1,2/1,2,1,2,1/2,1,2,1,x/x5 1 12").unwrap()
+    }
+
+    #[test]
+    fn no_legal_ply_ends_the_game_on_flats() {
+        // White has to place one of Black's flatstones, but Black has placed every one, all but
+        // three of them as standing stones.  Black still holds its capstone, so it isn't out of
+        // pieces.
+        let state =
+            tps::from_tps("2,2S,2S,2S,2S/2S,2S,2,2S,2S/2S,2S,2S,2S,2/2S,2S,2S,2S,2S/2S,x4 1 1")
+                .unwrap();
+
+        assert!(state.check_resolution().is_none());
+        assert!(movegen::generate_plies(&state).is_empty());
+
+        match game_result(&state) {
+            Some(Resolution::Flat(Color::Black)) => (),
+            resolution => panic!("expected a flat win for Black, got {:?}", resolution),
+        }
+    }
+
+    #[test]
+    fn komi_is_counted_in_half_flats() {
+        let state = ten_to_nine();
+
+        assert_eq!(flat_counts(&state), (10, 9));
+        assert_eq!(flat_margin(&state, 0), 2);
+        assert_eq!(flat_margin(&state, 1), 1);
+        assert_eq!(flat_margin(&state, 2), 0);
+        assert_eq!(flat_margin(&state, 3), -1);
+    }
+
+    #[test]
+    fn a_full_flat_of_komi_ties_a_one_flat_lead() {
+        let state = ten_to_nine();
+
+        assert_eq!(winner(&flat_result(&state, 1)), Some(Color::White));
+        assert!(is_draw(&flat_result(&state, 2)));
+        assert_eq!(winner(&flat_result(&state, 3)), Some(Color::Black));
+    }
+
+    #[test]
+    fn flat_win_margin_includes_komi() {
+        let state = ten_to_nine();
+        let white_win = Resolution::Flat(Color::White);
+        let black_win = Resolution::Flat(Color::Black);
+
+        assert_eq!(
+            flat_win_margin(&state, &white_win, 0),
+            Some((Color::White, 2))
+        );
+        assert_eq!(flat_win_margin(&state, &white_win, 2), None);
+        assert_eq!(
+            flat_win_margin(&state, &black_win, 3),
+            Some((Color::Black, 1))
+        );
+        assert_eq!(
+            flat_win_margin(&state, &Resolution::Road(Color::White), 0),
+            None
+        );
+    }
+
+    #[test]
+    fn apply_komi_rescores_only_flat_results() {
+        let state = ten_to_nine();
+
+        assert!(is_draw(&apply_komi(
+            &state,
+            Resolution::Flat(Color::White),
+            2
+        )));
+        assert_eq!(
+            winner(&apply_komi(&state, Resolution::Flat(Color::White), 3)),
+            Some(Color::Black),
+        );
+        assert_eq!(
+            winner(&apply_komi(&state, Resolution::Draw, -2)),
+            Some(Color::White),
+        );
+
+        match apply_komi(&state, Resolution::Road(Color::White), 100) {
+            Resolution::Road(Color::White) => (),
+            resolution => panic!("a road win was rescored as {:?}", resolution),
+        }
+    }
+
+    // White's row 3 and Black's column c both want c3
+    fn crossing_roads() -> State {
+        tps::from_tps("x2,2,x2/x2,2,x2/1,1,x,1,1/x2,2,x2/x2,2,x2 1 5").unwrap()
+    }
+
+    #[test]
+    fn a_capstone_completes_its_owners_road_and_blocks_the_opponents() {
+        let state = ptn::apply_ptn_move(&crossing_roads(), "Cc3").unwrap();
+
+        assert!(is_road_square(&state, 2, 2, Color::White));
+        assert!(!is_road_square(&state, 2, 2, Color::Black));
+        assert!(blocks_opponent_road(&state, 2, 2));
+        assert!(road_groups(&state, Color::Black)
+            .iter()
+            .all(|group| group.len() == 2));
+        match state.check_resolution() {
+            Some(Resolution::Road(Color::White)) => (),
+            resolution => panic!("the capstone's road ended the game as {:?}", resolution),
+        }
+    }
+
+    #[test]
+    fn a_standing_stone_blocks_both_roads() {
+        let state = ptn::apply_ptn_move(&crossing_roads(), "Sc3").unwrap();
+
+        assert!(!is_road_square(&state, 2, 2, Color::White));
+        assert!(!is_road_square(&state, 2, 2, Color::Black));
+        assert!(blocks_opponent_road(&state, 2, 2));
+        assert!(state.check_resolution().is_none());
+    }
+
+    #[test]
+    fn flatstones_and_empty_squares_dont_block_for_good() {
+        let state = crossing_roads();
+
+        // a3 is White's flatstone, which Black can capture, and c3 is empty
+        assert!(!blocks_opponent_road(&state, 0, 2));
+        assert!(!blocks_opponent_road(&state, 2, 2));
+    }
+
+    #[test]
+    fn completes_road_agrees_with_playing_each_ply_out() {
+        for size in 3..=6 {
+            for seed in 0..6 {
+                let (states, _, _) = self_play::play_random_game(size, seed).unwrap();
+
+                for state in states
+                    .iter()
+                    .filter(|state| state.check_resolution().is_none())
+                {
+                    for ply in movegen::generate_plies(state) {
+                        assert_eq!(
+                            completes_road(state, &[ply.clone()]),
+                            would_win_by_road(state, &ply),
+                            "{} in {}",
+                            ply.to_ptn(),
+                            tps::to_tps(state),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn influence_spreads_through_empty_squares_only() {
+        // a1 is White's only road square, boxed in by Black except for b1; White's own standing
+        // stone on c2 stops both colors
+        let state = tps::from_tps("x,2,x/2,2,1S/1,x,2 1 4").unwrap();
+
+        assert_eq!(
+            influence(&state, Color::White),
+            vec![
+                vec![true, false, false],
+                vec![true, false, false],
+                vec![false, false, false],
+            ],
+        );
+        assert_eq!(
+            influence(&state, Color::Black),
+            vec![
+                vec![false, true, true],
+                vec![true, true, true],
+                vec![true, false, true],
+            ],
+        );
+    }
+    #[test]
+    fn a_line_of_standing_stones_cuts_off_the_squares_beyond() {
+        let state = tps::from_tps("x4/x,2,x2/2S,2S,2S,2S/1,x3 1 4").unwrap();
+
+        // White can't get past the second rank, but nothing of White's stops Black
+        assert_eq!(
+            reachable_for_road(&state, Color::White),
+            vec![vec![true, false, false, false]; 4],
+        );
+        assert_eq!(
+            reachable_for_road(&state, Color::Black),
+            vec![vec![true; 4]; 4]
+        );
+    }
+}
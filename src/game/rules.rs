@@ -0,0 +1,865 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::impls::tak::{Color, Direction, Piece, Ply, State};
+use zero_sum::State as StateTrait;
+
+// The (flatstone, capstone) reserves each player starts with on a board of the given size
+pub fn starting_reserves(board_size: usize) -> Option<(u32, u32)> {
+    match board_size {
+        3 => Some((10, 0)),
+        4 => Some((15, 0)),
+        5 => Some((21, 1)),
+        6 => Some((30, 1)),
+        7 => Some((40, 2)),
+        8 => Some((50, 2)),
+        _ => None,
+    }
+}
+
+// State::new panics on unsupported sizes; this reports them instead
+pub fn new_state(board_size: usize) -> Result<State, String> {
+    if starting_reserves(board_size).is_some() {
+        Ok(State::new(board_size))
+    } else {
+        Err(format!("Invalid board size: {}.", board_size))
+    }
+}
+
+pub fn piece_color(piece: &Piece) -> Color {
+    match *piece {
+        Piece::Flatstone(color) | Piece::StandingStone(color) | Piece::Capstone(color) => color,
+    }
+}
+
+pub fn side_to_move(state: &State) -> Color {
+    if state.ply_count % 2 == 0 {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
+
+// Whether two states have the same stacks, and so the same reserves, however many plies it took
+// to reach them.  zero_sum's cached analysis of the position is ignored.
+pub fn board_eq(a: &State, b: &State) -> bool {
+    a.board == b.board
+}
+
+// board_eq, and the same player to move
+pub fn position_eq(a: &State, b: &State) -> bool {
+    board_eq(a, b) && side_to_move(a) == side_to_move(b)
+}
+
+// The player who isn't to move
+pub fn opponent(state: &State) -> Color {
+    side_to_move(state).flip()
+}
+
+// Accepts w/b and PTN's 1/2 for White and Black
+pub fn color_from_char(c: char) -> Option<Color> {
+    match c {
+        'w' | 'W' | '1' => Some(Color::White),
+        'b' | 'B' | '2' => Some(Color::Black),
+        _ => None,
+    }
+}
+
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+// Pieces are written as the board display writes them: W or B, then S for a standing stone or C
+// for a capstone.  Unlike color_from_char, only W and B are accepted.
+
+pub fn color_glyph(color: Color) -> char {
+    match color {
+        Color::White => 'W',
+        Color::Black => 'B',
+    }
+}
+
+pub fn parse_color(glyph: &str) -> Result<Color, String> {
+    match glyph {
+        "W" => Ok(Color::White),
+        "B" => Ok(Color::Black),
+        _ => Err(format!("Unknown color: \"{}\".", glyph)),
+    }
+}
+
+pub fn piece_glyph(piece: &Piece) -> String {
+    let mut glyph = color_glyph(piece_color(piece)).to_string();
+
+    match *piece {
+        Piece::Flatstone(_) => (),
+        Piece::StandingStone(_) => glyph.push('S'),
+        Piece::Capstone(_) => glyph.push('C'),
+    }
+
+    glyph
+}
+
+pub fn parse_piece(glyph: &str) -> Result<Piece, String> {
+    let error = || format!("Unknown piece: \"{}\".", glyph);
+
+    let color = match glyph.get(..1) {
+        Some(color) => parse_color(color).map_err(|_| error())?,
+        None => return Err(error()),
+    };
+
+    match &glyph[1..] {
+        "" => Ok(Piece::Flatstone(color)),
+        "S" => Ok(Piece::StandingStone(color)),
+        "C" => Ok(Piece::Capstone(color)),
+        _ => Err(error()),
+    }
+}
+
+// Board accessors that treat squares off the board as empty instead of panicking
+
+pub fn stack_height(state: &State, x: usize, y: usize) -> usize {
+    state
+        .board
+        .get(x)
+        .and_then(|column| column.get(y))
+        .map_or(0, |stack| stack.len())
+}
+
+pub fn top_piece(state: &State, x: usize, y: usize) -> Option<&Piece> {
+    state
+        .board
+        .get(x)
+        .and_then(|column| column.get(y))
+        .and_then(|stack| stack.last())
+}
+
+// The color of the square's top piece
+pub fn controller(state: &State, x: usize, y: usize) -> Option<Color> {
+    top_piece(state, x, y).map(piece_color)
+}
+
+// The squares whose top piece is the color's, of any kind, indexed [x][y].  Covered pieces don't
+// count, so every occupied square belongs to exactly one color.
+pub fn controlled_squares(state: &State, color: Color) -> Vec<Vec<bool>> {
+    state
+        .board
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|stack| stack.last().map(piece_color) == Some(color))
+                .collect()
+        })
+        .collect()
+}
+
+// The same kind of piece in the other color
+pub fn opposite_color(piece: &Piece) -> Piece {
+    with_color(piece, piece_color(piece).flip())
+}
+
+pub fn with_color(piece: &Piece, color: Color) -> Piece {
+    match *piece {
+        Piece::Flatstone(_) => Piece::Flatstone(color),
+        Piece::StandingStone(_) => Piece::StandingStone(color),
+        Piece::Capstone(_) => Piece::Capstone(color),
+    }
+}
+
+// Standing stones and capstones can't be stacked upon
+pub fn is_blocking(piece: &Piece) -> bool {
+    match *piece {
+        Piece::StandingStone(_) | Piece::Capstone(_) => true,
+        Piece::Flatstone(_) => false,
+    }
+}
+
+// What a piece becomes when a capstone lands on it; only standing stones actually change
+pub fn flatten(piece: &Piece) -> Piece {
+    match *piece {
+        Piece::StandingStone(color) => Piece::Flatstone(color),
+        ref piece => piece.clone(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PieceCounts {
+    pub flatstones: u32,
+    pub standing_stones: u32,
+    pub capstones: u32,
+}
+
+// Every piece on the board, covered or not, by type and color
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Census {
+    pub white: PieceCounts,
+    pub black: PieceCounts,
+}
+
+impl Census {
+    pub fn of(&self, color: Color) -> &PieceCounts {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+}
+
+pub fn census(state: &State) -> Census {
+    let mut census = Census::default();
+
+    for piece in state
+        .board
+        .iter()
+        .flat_map(|column| column.iter())
+        .flat_map(|stack| stack.iter())
+    {
+        let counts = match piece_color(piece) {
+            Color::White => &mut census.white,
+            Color::Black => &mut census.black,
+        };
+
+        match *piece {
+            Piece::Flatstone(_) => counts.flatstones += 1,
+            Piece::StandingStone(_) => counts.standing_stones += 1,
+            Piece::Capstone(_) => counts.capstones += 1,
+        }
+    }
+
+    census
+}
+
+// The (flatstone, capstone) counts a player has left to place.  Standing stones come out of the
+// flatstone reserve.
+pub fn remaining_pieces(state: &State, color: Color) -> (u32, u32) {
+    let (flatstones, capstones) = starting_reserves(state.board.len()).unwrap_or((0, 0));
+    let census = census(state);
+    let counts = census.of(color);
+
+    (
+        flatstones.saturating_sub(counts.flatstones + counts.standing_stones),
+        capstones.saturating_sub(counts.capstones),
+    )
+}
+
+// Every stone the player has left, of any kind.  Whether a particular piece can still be placed
+// depends on its own reserve, so use remaining_pieces for that.
+pub fn pieces_remaining_total(state: &State, color: Color) -> u32 {
+    let (flatstones, capstones) = remaining_pieces(state, color);
+    flatstones + capstones
+}
+
+// The squares the side to move could place a stone on, indexed [x][y], for highlighting in a UI.
+// This is every empty square, or none if the reserve that would supply the stone is used up.  In
+// the opening that's the opponent's flatstones.
+pub fn placeable_squares(state: &State) -> Vec<Vec<bool>> {
+    let can_place = if state.ply_count < 2 {
+        remaining_pieces(state, opponent(state)).0 > 0
+    } else {
+        pieces_remaining_total(state, side_to_move(state)) > 0
+    };
+
+    state
+        .board
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|stack| can_place && stack.is_empty())
+                .collect()
+        })
+        .collect()
+}
+
+// A rough count of the plies left for the side to move.  A game on flats ends when either player
+// runs out of stones or the board fills, so every placement brings it closer; a board's worth of
+// slides is allowed on top of that.
+pub fn estimate_remaining_plies(state: &State) -> usize {
+    let board_size = state.board.len();
+    let empty_squares = state
+        .board
+        .iter()
+        .flat_map(|column| column.iter())
+        .filter(|stack| stack.is_empty())
+        .count();
+
+    let reserves = pieces_remaining_total(state, Color::White)
+        .min(pieces_remaining_total(state, Color::Black)) as usize;
+
+    (empty_squares.min(reserves * 2) + board_size) / 2
+}
+
+// Whether the ply ends the game by using up the last of the mover's pieces or by filling the last
+// empty square.  The ply is assumed to be legal.
+pub fn would_end_game(state: &State, ply: &Ply) -> bool {
+    let empty_squares = state
+        .board
+        .iter()
+        .flat_map(|column| column.iter())
+        .filter(|stack| stack.is_empty())
+        .count();
+
+    match *ply {
+        Ply::Place { ref piece, .. } => {
+            let (flatstones, capstones) = remaining_pieces(state, piece_color(piece));
+            flatstones + capstones == 1 || empty_squares == 1
+        }
+        Ply::Slide {
+            x, y, ref drops, ..
+        } => {
+            let covered = covered_squares(ply)
+                .into_iter()
+                .filter(|&(next_x, next_y)| state.board[next_x][next_y].is_empty())
+                .count();
+
+            let grab = drops.iter().map(|&drop| drop as usize).sum::<usize>();
+            let vacated = if grab == state.board[x][y].len() {
+                1
+            } else {
+                0
+            };
+
+            empty_squares + vacated == covered
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlyEffect {
+    Flattened { x: usize, y: usize },
+}
+
+// The side effects a legal ply has beyond moving stones, worked out from the state before it's
+// executed.  Only a capstone dropped alone onto a standing stone flattens it.
+pub fn ply_effects(state: &State, ply: &Ply) -> Vec<PlyEffect> {
+    let mut effects = Vec::new();
+
+    if let Ply::Slide {
+        x, y, ref drops, ..
+    } = *ply
+    {
+        let (target_x, target_y) = *covered_squares(ply).last().unwrap();
+
+        let capstone_alone = drops.last() == Some(&1)
+            && match state.board[x][y].last() {
+                Some(&Piece::Capstone(_)) => true,
+                _ => false,
+            };
+
+        if let Some(&Piece::StandingStone(_)) = state.board[target_x][target_y].last() {
+            if capstone_alone {
+                effects.push(PlyEffect::Flattened {
+                    x: target_x,
+                    y: target_y,
+                });
+            }
+        }
+    }
+
+    effects
+}
+
+// Constructors that spare callers from building Ply variants by hand
+
+pub fn place_ply(x: usize, y: usize, piece: Piece) -> Ply {
+    Ply::Place {
+        x: x,
+        y: y,
+        piece: piece,
+    }
+}
+
+pub fn place_flat_ply(x: usize, y: usize, color: Color) -> Ply {
+    place_ply(x, y, Piece::Flatstone(color))
+}
+
+// Rejects drops that couldn't describe any slide; whether the slide is legal on a particular board
+// is still up to validate_ply
+pub fn slide_ply(x: usize, y: usize, direction: Direction, drops: Vec<u8>) -> Result<Ply, String> {
+    if drops.is_empty() || drops.iter().any(|&drop| drop == 0) {
+        return Err(String::from("Every drop must leave at least one stone."));
+    }
+
+    Ok(Ply::Slide {
+        x: x,
+        y: y,
+        direction: direction,
+        drops: drops,
+    })
+}
+
+pub fn ply_origin(ply: &Ply) -> (usize, usize) {
+    match *ply {
+        Ply::Place { x, y, .. } | Ply::Slide { x, y, .. } => (x, y),
+    }
+}
+
+// The square a placement fills, or every square a slide drops stones on, in order.  The ply is
+// assumed to stay on the board.
+pub fn covered_squares(ply: &Ply) -> Vec<(usize, usize)> {
+    match *ply {
+        Ply::Place { x, y, .. } => vec![(x, y)],
+        Ply::Slide {
+            x,
+            y,
+            ref direction,
+            ref drops,
+        } => {
+            let (dx, dy) = direction_offset(direction);
+
+            (1..=drops.len() as isize)
+                .map(|i| {
+                    (
+                        (x as isize + dx * i) as usize,
+                        (y as isize + dy * i) as usize,
+                    )
+                })
+                .collect()
+        }
+    }
+}
+
+pub fn all_directions() -> [Direction; 4] {
+    [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ]
+}
+
+pub fn direction_offset(direction: &Direction) -> (isize, isize) {
+    match *direction {
+        Direction::North => (0, 1),
+        Direction::East => (1, 0),
+        Direction::South => (0, -1),
+        Direction::West => (-1, 0),
+    }
+}
+
+// The first two plies must each place a flatstone of the opponent's color
+pub fn validate_opening(state: &State, ply: &Ply) -> Result<(), String> {
+    if state.ply_count >= 2 {
+        return Ok(());
+    }
+
+    let side_to_move = side_to_move(state);
+
+    match *ply {
+        Ply::Place {
+            piece: Piece::Flatstone(color),
+            ..
+        } if color != side_to_move => Ok(()),
+        _ => Err(String::from("Illegal opening move.")),
+    }
+}
+
+// Executes the ply only if validate_ply accepts it.  execute_ply alone lets through plies it
+// can't have come from PTN, such as a slide with no drops, which would pass the turn without moving
+// anything.
+pub fn execute_validated(state: &mut State, ply: &Ply) -> Result<(), String> {
+    validate_ply(state, ply)?;
+    state.execute_ply(Some(ply))?;
+
+    #[cfg(feature = "debug-analysis")]
+    debug_assert_analysis(state);
+
+    Ok(())
+}
+
+// Checks that zero_sum's incrementally updated analysis agrees with one rebuilt from the board.
+// The analysis itself is private to zero_sum, so it's compared through what it decides, the game's
+// resolution: against a state rebuilt from TPS, and against the road groups, piece census, and
+// reserves counted from the board.
+#[cfg(any(test, feature = "debug-analysis"))]
+fn debug_assert_analysis(state: &State) {
+    use super::{resolution, tps};
+    use zero_sum::impls::tak::Resolution;
+
+    let rebuilt = match tps::rebuild_analysis(state) {
+        Ok(rebuilt) => rebuilt,
+        Err(error) => panic!("Couldn't rebuild the analysis: {}", error),
+    };
+
+    let decided = state.check_resolution();
+    let position = tps::to_tps(state);
+
+    debug_assert_eq!(
+        decided
+            .as_ref()
+            .map(|resolution| resolution::to_ptn_result(resolution)),
+        rebuilt
+            .check_resolution()
+            .map(|resolution| resolution::to_ptn_result(&resolution)),
+        "analysis is out of date after ply {}: {}",
+        state.ply_count,
+        position,
+    );
+
+    let board_size = state.board.len();
+    let (flatstones, capstones) = starting_reserves(board_size).unwrap_or((0, 0));
+    let census = census(state);
+
+    for &color in [Color::White, Color::Black].iter() {
+        let counts = census.of(color);
+        debug_assert!(
+            counts.flatstones + counts.standing_stones <= flatstones
+                && counts.capstones <= capstones,
+            "{} has more pieces on the board than it started with: {}",
+            color_name(color),
+            position,
+        );
+    }
+
+    let roads = [Color::White, Color::Black]
+        .iter()
+        .cloned()
+        .filter(|&color| {
+            resolution::road_groups(state, color)
+                .iter()
+                .any(|group| resolution::is_road(group, board_size))
+        })
+        .collect::<Vec<_>>();
+    let out_of_pieces = [Color::White, Color::Black]
+        .iter()
+        .any(|&color| remaining_pieces(state, color) == (0, 0));
+    let board_full = state
+        .board
+        .iter()
+        .flat_map(|column| column.iter())
+        .all(|stack| !stack.is_empty());
+
+    match decided {
+        Some(Resolution::Road(color)) => debug_assert!(
+            roads.contains(&color),
+            "analysis finds a road the board doesn't have: {}",
+            position,
+        ),
+        decided => {
+            debug_assert!(
+                roads.is_empty(),
+                "analysis misses a road on the board: {}",
+                position,
+            );
+            debug_assert_eq!(
+                decided.is_some(),
+                out_of_pieces || board_full,
+                "analysis disagrees with the reserves and the board about whether the game is over: {}",
+                position,
+            );
+        }
+    }
+}
+
+// Plays the plies in order from a new board, reporting the zero-based index of the first one that
+// fails along with why
+pub fn from_plies_indexed(board_size: usize, plies: &[Ply]) -> Result<State, (usize, String)> {
+    let mut state = new_state(board_size).map_err(|error| (0, error))?;
+    execute_plies_indexed(&mut state, plies)?;
+    Ok(state)
+}
+
+pub fn from_plies(board_size: usize, plies: &[Ply]) -> Result<State, String> {
+    from_plies_indexed(board_size, plies).map_err(|(_, error)| error)
+}
+
+// execute_validated for each ply in turn, stopping at the first failure and reporting its index
+pub fn execute_plies_indexed(state: &mut State, plies: &[Ply]) -> Result<(), (usize, String)> {
+    for (index, ply) in plies.iter().enumerate() {
+        execute_validated(state, ply).map_err(|error| (index, error))?;
+    }
+
+    Ok(())
+}
+
+// Checks a ply against the rules without executing it, so that candidate moves can be tested
+// without cloning the state
+pub fn validate_ply(state: &State, ply: &Ply) -> Result<(), String> {
+    let board_size = state.board.len();
+    let side_to_move = side_to_move(state);
+
+    match *ply {
+        Ply::Place { x, y, ref piece } => {
+            if x >= board_size || y >= board_size {
+                return Err(String::from("Out of bounds."));
+            }
+
+            if !state.board[x][y].is_empty() {
+                return Err(String::from("Cannot place on an occupied square."));
+            }
+
+            validate_opening(state, ply)?;
+
+            let color = piece_color(piece);
+            if state.ply_count >= 2 && color != side_to_move {
+                return Err(String::from(
+                    "Cannot place a piece of the opponent's color.",
+                ));
+            }
+
+            let (flatstones, capstones) = remaining_pieces(state, color);
+            match *piece {
+                Piece::Capstone(_) if capstones == 0 => {
+                    Err(String::from("No capstones remaining."))
+                }
+                Piece::Flatstone(_) | Piece::StandingStone(_) if flatstones == 0 => {
+                    Err(String::from("No flatstones remaining."))
+                }
+                _ => Ok(()),
+            }
+        }
+        Ply::Slide {
+            x,
+            y,
+            ref direction,
+            ref drops,
+        } => {
+            validate_opening(state, ply)?;
+
+            if x >= board_size || y >= board_size {
+                return Err(String::from("Out of bounds."));
+            }
+
+            let stack = &state.board[x][y];
+            let top = match stack.last() {
+                Some(top) => top,
+                None => return Err(String::from("Cannot move an empty square.")),
+            };
+
+            if piece_color(top) != side_to_move {
+                return Err(String::from(
+                    "Cannot move a stack controlled by the opponent.",
+                ));
+            }
+
+            if drops.is_empty() || drops.iter().any(|&drop| drop == 0) {
+                return Err(String::from("Every drop must leave at least one stone."));
+            }
+
+            let grab = drops.iter().map(|&drop| drop as usize).sum::<usize>();
+            if grab > board_size {
+                return Err(String::from(
+                    "Cannot carry more stones than the board size.",
+                ));
+            }
+
+            if grab > stack.len() {
+                return Err(String::from(
+                    "Cannot carry more stones than the stack holds.",
+                ));
+            }
+
+            let (dx, dy) = direction_offset(direction);
+
+            for (i, &drop) in drops.iter().enumerate() {
+                let next_x = x as isize + dx * (i as isize + 1);
+                let next_y = y as isize + dy * (i as isize + 1);
+
+                if next_x < 0
+                    || next_y < 0
+                    || next_x >= board_size as isize
+                    || next_y >= board_size as isize
+                {
+                    return Err(String::from("Cannot slide off the board."));
+                }
+
+                match state.board[next_x as usize][next_y as usize].last() {
+                    Some(target) if is_blocking(target) => {
+                        // A capstone is the one blocking piece that flattening leaves as it is
+                        if flatten(target) == *target {
+                            return Err(String::from("Cannot slide onto a capstone."));
+                        }
+
+                        // Only a capstone dropped alone at the end of a slide can flatten a wall
+                        let flattens = i == drops.len() - 1
+                            && drop == 1
+                            && match *top {
+                                Piece::Capstone(_) => true,
+                                _ => false,
+                            };
+
+                        if !flattens {
+                            return Err(String::from("Cannot slide onto a standing stone."));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{movegen, ptn, resolution, self_play, tps};
+
+    // Games with placements, walls, capstones, and slides, one of them ending in a road
+    const GAMES: [(usize, &[&str]); 4] = [
+        (3, &["c3", "a1", "b1", "a3", "c1"]),
+        (4, &["a1", "d4", "b2", "c3", "Sb3", "c2", "b2>", "b4"]),
+        (
+            5,
+            &[
+                "a1", "e5", "c3", "c4", "c3+", "d4", "2c4>", "Sb4", "e5<", "b4>",
+            ],
+        ),
+        (6, &["f6", "a1", "Cc3", "Cd4", "c3<", "d4-"]),
+    ];
+
+    #[test]
+    fn from_plies_matches_a_ply_by_ply_replay() {
+        for &(size, moves) in GAMES.iter() {
+            let start = new_state(size).unwrap();
+            let (_, plies) = ptn::play_moves(&start, moves, true).unwrap();
+
+            let mut replayed = start.clone();
+            for ply in plies.iter() {
+                replayed.execute_ply(Some(ply)).unwrap();
+            }
+
+            let built = from_plies(size, &plies).unwrap();
+            assert!(position_eq(&built, &replayed), "{:?}", moves);
+            assert_eq!(built.ply_count, replayed.ply_count);
+            assert_eq!(tps::to_tps(&built), tps::to_tps(&replayed));
+
+            // A state rebuilt from the board alone decides the game the same way
+            let rebuilt = tps::rebuild_analysis(&built).unwrap();
+            assert_eq!(
+                resolution::to_ptn_result_or_ongoing(built.check_resolution().as_ref()),
+                resolution::to_ptn_result_or_ongoing(rebuilt.check_resolution().as_ref()),
+            );
+        }
+    }
+
+    #[test]
+    fn from_plies_indexed_reports_the_ply_that_failed() {
+        let (size, moves) = GAMES[2];
+        let start = new_state(size).unwrap();
+        let (_, mut plies) = ptn::play_moves(&start, moves, true).unwrap();
+
+        // a1 is taken
+        plies.push(place_flat_ply(0, 0, Color::White));
+        assert_eq!(from_plies_indexed(size, &plies).unwrap_err().0, moves.len());
+    }
+
+    #[test]
+    fn analysis_matches_the_board_through_random_games() {
+        for size in 3..=8 {
+            for seed in 0..8 {
+                let (states, _, _) = self_play::play_random_game(size, seed).unwrap();
+
+                for state in states.iter() {
+                    debug_assert_analysis(state);
+                }
+            }
+        }
+    }
+    #[test]
+    fn flattening_changes_only_standing_stones() {
+        let pieces = [
+            Piece::Flatstone(Color::White),
+            Piece::StandingStone(Color::Black),
+            Piece::Capstone(Color::White),
+        ];
+
+        assert_eq!(flatten(&pieces[0]), pieces[0]);
+        assert_eq!(flatten(&pieces[1]), Piece::Flatstone(Color::Black));
+        assert_eq!(flatten(&pieces[2]), pieces[2]);
+    }
+
+    // White to move, with a two-stone stack White controls on c4
+    fn two_stone_stack() -> State {
+        let start = new_state(5).unwrap();
+        let moves = ["a1", "e5", "c3", "c4", "c3+", "b1"];
+
+        ptn::play_moves(&start, &moves, true).unwrap().0
+    }
+
+    fn c4_slide(drops: Vec<u8>) -> Ply {
+        Ply::Slide {
+            x: 2,
+            y: 3,
+            direction: Direction::South,
+            drops: drops,
+        }
+    }
+
+    #[test]
+    fn rejects_slides_that_drop_no_stones() {
+        let state = two_stone_stack();
+
+        for drops in vec![vec![], vec![0, 1], vec![1, 0]] {
+            let ply = c4_slide(drops);
+            assert_eq!(
+                validate_ply(&state, &ply),
+                Err(String::from("Every drop must leave at least one stone.")),
+            );
+
+            let mut next = state.clone();
+            assert!(execute_validated(&mut next, &ply).is_err());
+            assert_eq!(next.ply_count, state.ply_count);
+        }
+    }
+    #[test]
+    fn rejects_carrying_more_than_the_stack_holds() {
+        let state = two_stone_stack();
+        let ply = c4_slide(vec![2, 1]);
+
+        assert_eq!(
+            validate_ply(&state, &ply),
+            Err(String::from(
+                "Cannot carry more stones than the stack holds."
+            )),
+        );
+
+        let mut next = state.clone();
+        assert!(execute_validated(&mut next, &ply).is_err());
+        assert_eq!(next.board[2][3].len(), 2);
+        assert!(validate_ply(&state, &c4_slide(vec![1, 1])).is_ok());
+    }
+    #[test]
+    fn the_analysis_after_a_ply_equals_one_rebuilt_from_the_board() {
+        for size in 3..=6 {
+            for seed in 0..2 {
+                let (states, _, _) = self_play::play_random_game(size, seed).unwrap();
+
+                for state in states.iter().skip(1) {
+                    let rebuilt = tps::rebuild_analysis(state).unwrap();
+
+                    assert!(position_eq(state, &rebuilt));
+                    assert_eq!(
+                        resolution::to_ptn_result_or_ongoing(state.check_resolution().as_ref()),
+                        resolution::to_ptn_result_or_ongoing(rebuilt.check_resolution().as_ref()),
+                    );
+                    // Extrapolation reads the analysis too
+                    let plies = movegen::generate_plies(state);
+                    let rebuilt_plies = movegen::generate_plies(&rebuilt);
+                    assert_eq!(plies.len(), rebuilt_plies.len(), "{}", tps::to_tps(state));
+                    assert!(plies.iter().all(|ply| rebuilt_plies.contains(ply)));
+                }
+            }
+        }
+    }
+}
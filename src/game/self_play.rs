@@ -0,0 +1,158 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::sync::mpsc::{self, Sender};
+
+use zero_sum::impls::tak::{Color, Ply, Resolution, State};
+
+use crate::player::{Player, SplitMix64};
+
+use super::resolution::{self, GameResult};
+use super::{movegen, rules, zobrist, Message};
+
+// Random games that haven't ended by this many plies are cut off
+pub const RANDOM_GAME_PLY_LIMIT: usize = 1000;
+
+#[derive(Clone, Debug)]
+pub enum MatchOutcome {
+    Finished(Resolution),
+    // The player made an illegal move or left the game
+    Forfeit(Color, String),
+}
+
+impl MatchOutcome {
+    // A forfeit is recorded as a resignation
+    pub fn game_result(&self) -> GameResult {
+        match *self {
+            MatchOutcome::Finished(ref resolution) => GameResult::Board(resolution.clone()),
+            MatchOutcome::Forfeit(color, _) => GameResult::Resignation(color),
+        }
+    }
+}
+
+// Plays a game between two players without the interactive game loop, for regression and
+// strength testing.  Every ply is checked before it's played, and a player whose ply is
+// illegal forfeits.  Repeated positions end the game in a draw, so shuffling engines can't
+// keep a match going forever.
+pub fn run_match(
+    p1: &mut dyn Player,
+    p2: &mut dyn Player,
+    size: usize,
+) -> Result<(Vec<Ply>, MatchOutcome), String> {
+    let mut state = rules::new_state(size)?;
+    let mut history = vec![zobrist::zobrist_hash(&state)];
+    let mut plies = Vec::new();
+
+    let (to_game, receiver) = mpsc::channel();
+    let p1_sender = p1.initialize(to_game.clone(), &*p2)?;
+    let p2_sender = p2.initialize(to_game, &*p1)?;
+
+    let send = |color, message| {
+        let sender: &Sender<Message> = match color {
+            Color::White => &p1_sender,
+            Color::Black => &p2_sender,
+        };
+        sender.send(message).ok();
+    };
+
+    send(Color::White, Message::GameStart(Color::White));
+    send(Color::Black, Message::GameStart(Color::Black));
+
+    let outcome = loop {
+        let color = rules::side_to_move(&state);
+        send(color, Message::MoveRequest(state.clone()));
+
+        let ply = loop {
+            match receiver.recv() {
+                Ok((sender, message)) if sender == color => match message {
+                    Message::MoveResponse(ply) => break Some(ply),
+                    Message::MoveResponseSearch(result) => break Some(result.best_ply),
+                    Message::MoveResponseMultiPv(lines) => {
+                        break lines.into_iter().next().map(|line| line.best_ply)
+                    }
+                    Message::GameOver => break None,
+                    _ => (),
+                },
+                Ok(_) => (),
+                Err(_) => break None,
+            }
+        };
+
+        let ply = match ply {
+            Some(ply) => ply,
+            None => break MatchOutcome::Forfeit(color, String::from("Left the game.")),
+        };
+
+        let result = rules::execute_validated(&mut state, &ply);
+
+        if let Err(error) = result {
+            break MatchOutcome::Forfeit(
+                color,
+                format!("Illegal move {}: {}", ply.to_ptn(), error),
+            );
+        }
+
+        plies.push(ply.clone());
+        history.push(zobrist::zobrist_hash(&state));
+        send(color.flip(), Message::MoveResponse(ply));
+
+        if let Some(resolution) = resolution::game_result(&state) {
+            break MatchOutcome::Finished(resolution);
+        }
+
+        if zobrist::is_repetition_draw(&history, zobrist::REPETITION_LIMIT) {
+            break MatchOutcome::Finished(Resolution::Draw);
+        }
+    };
+
+    send(Color::White, Message::GameOver);
+    send(Color::Black, Message::GameOver);
+
+    Ok((plies, outcome))
+}
+
+// Plays uniformly random legal plies from an empty board until the game ends or
+// RANDOM_GAME_PLY_LIMIT is reached, for fuzzing and for generating test positions.  Returns every
+// state from the start onward, the plies between them, and the result, which is None if the game
+// was cut off.  The same size and seed always give the same game.
+pub fn play_random_game(
+    size: usize,
+    seed: u64,
+) -> Result<(Vec<State>, Vec<Ply>, Option<Resolution>), String> {
+    let mut rng = SplitMix64::new(seed);
+    let mut state = rules::new_state(size)?;
+    let mut states = vec![state.clone()];
+    let mut plies = Vec::new();
+
+    while plies.len() < RANDOM_GAME_PLY_LIMIT {
+        if let Some(resolution) = resolution::game_result(&state) {
+            return Ok((states, plies, Some(resolution)));
+        }
+
+        let mut candidates = movegen::generate_plies(&state);
+        let ply = candidates.swap_remove(rng.below(candidates.len()));
+
+        rules::execute_validated(&mut state, &ply)?;
+        states.push(state.clone());
+        plies.push(ply);
+    }
+
+    let resolution = resolution::game_result(&state);
+    Ok((states, plies, resolution))
+}
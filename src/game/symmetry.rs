@@ -0,0 +1,112 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::impls::tak::State;
+
+use super::tps;
+
+// Mirrors the board left to right
+pub fn flip_horizontal(state: &State) -> State {
+    transform(state, |x, y, board_size| (board_size - 1 - x, y))
+}
+
+// Mirrors the board top to bottom
+pub fn flip_vertical(state: &State) -> State {
+    transform(state, |x, y, board_size| (x, board_size - 1 - y))
+}
+
+// Rotates the board a quarter turn clockwise
+pub fn rotate_90(state: &State) -> State {
+    transform(state, |x, y, board_size| (y, board_size - 1 - x))
+}
+
+// All eight orientations of the board, starting with the state as given
+pub fn symmetries(state: &State) -> Vec<State> {
+    let mut rotations = vec![state.clone()];
+    for _ in 0..3 {
+        let next = rotate_90(rotations.last().unwrap());
+        rotations.push(next);
+    }
+
+    let reflections = rotations.iter().map(flip_horizontal).collect::<Vec<_>>();
+
+    rotations.extend(reflections);
+    rotations
+}
+
+// The orientation with the lexicographically smallest TPS, so that symmetric positions share a
+// single representative
+pub fn canonical(state: &State) -> State {
+    symmetries(state)
+        .into_iter()
+        .min_by_key(|state| tps::to_tps(state))
+        .unwrap()
+}
+
+// Moves the stack on each (x, y) to map(x, y, board_size), then has zero_sum recompute its
+// analysis of the new board
+fn transform<F>(state: &State, map: F) -> State
+where
+    F: Fn(usize, usize, usize) -> (usize, usize),
+{
+    let board_size = state.board.len();
+    let mut transformed = state.clone();
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            let (new_x, new_y) = map(x, y, board_size);
+            transformed.board[new_x][new_y] = state.board[x][y].clone();
+        }
+    }
+
+    tps::rebuild_analysis(&transformed).expect("Symmetric state should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zero_sum::impls::tak::{Color, Resolution};
+    use zero_sum::State as StateTrait;
+
+    #[test]
+    fn a_road_is_a_road_in_every_orientation() {
+        let state = tps::from_tps("x5/x5/x5/2,2,2,x2/1,1,1,1,1 2 6").unwrap();
+
+        for symmetry in symmetries(&state) {
+            match symmetry.check_resolution() {
+                Some(Resolution::Road(Color::White)) => (),
+                resolution => panic!(
+                    "{} should be White's road, but is {:?}",
+                    tps::to_tps(&symmetry),
+                    resolution,
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn every_orientation_has_the_same_canonical_form() {
+        let state = tps::from_tps("x5/x,2S,1,1C,x/x,1,21,2C,x/x,2,x3/x5 1 7").unwrap();
+        let expected = tps::to_tps(&canonical(&state));
+
+        for symmetry in symmetries(&state) {
+            assert_eq!(tps::to_tps(&canonical(&symmetry)), expected);
+        }
+    }
+}
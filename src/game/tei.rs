@@ -1,10 +1,17 @@
-use crate::game::{Message, State};
-use crate::player::{Player, PvSearchPlayer};
+use crate::game::{
+    ptn, rules, tps, zobrist, Message, SearchLimit, SearchResult, State, TimePolicy,
+};
+use crate::player::{plies_to_road, Bound, Player, PvSearchPlayer, SearchOptions};
 use crate::Color;
-use crate::Ply;
 use std::any::Any;
-use std::sync::mpsc::{channel, Receiver, RecvError, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
 use std::thread;
+use std::time::Duration;
+
+// The largest value identify advertises for each spin option; setoption rejects anything above it
+const MAX_HASH_SIZE: usize = 4096;
+const MAX_THREADS: usize = 64;
+const MAX_MULTIPV: usize = 256;
 
 struct DummyPlayer {}
 
@@ -29,8 +36,10 @@ pub enum TeiCommand {
     Stop,
     Quit,
     Go(String),
+    PonderHit,
     Position(String),
     NewGame(usize),
+    SetOption(String, String),
 }
 
 struct TimeLeft {
@@ -62,13 +71,42 @@ impl TimeLeft {
         }
         ret
     }
-    fn use_time(&self, est_plies: usize, side_to_move: Color) -> u64 {
+    fn policy(&self, side_to_move: Color) -> TimePolicy {
         let (time_bank, inc) = match side_to_move {
             Color::White => (self.wtime, self.winc),
             Color::Black => (self.btime, self.binc),
         };
-        let use_bank = time_bank / (est_plies + 2) as u64 / 1000;
-        use_bank + inc / 1000
+        TimePolicy::Clock {
+            remaining: Duration::from_millis(time_bank),
+            increment: Duration::from_millis(inc),
+        }
+    }
+}
+
+// Picks the search limit from the arguments to go: movetime, depth, nodes, and infinite are used as
+// given, and otherwise the time is budgeted from the clock
+fn parse_go(go: &str, state: &State) -> SearchLimit {
+    let tokens = go.split_whitespace().collect::<Vec<_>>();
+    let argument = |name: &str| {
+        tokens
+            .iter()
+            .position(|&token| token == name)
+            .and_then(|index| tokens.get(index + 1))
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+
+    if tokens.contains(&"infinite") {
+        TimePolicy::Infinite.limit(state)
+    } else if let Some(movetime) = argument("movetime") {
+        TimePolicy::MoveTime(Duration::from_millis(movetime)).limit(state)
+    } else if let Some(depth) = argument("depth") {
+        SearchLimit::Depth(depth.min(u8::max_value() as u64) as u8)
+    } else if let Some(nodes) = argument("nodes") {
+        SearchLimit::Nodes(nodes)
+    } else {
+        TimeLeft::new(go)
+            .policy(rules::side_to_move(state))
+            .limit(state)
     }
 }
 
@@ -80,59 +118,209 @@ pub fn play_game_tei(tei_receiver: Receiver<TeiCommand>) -> Result<(), RecvError
         .expect("Failed to init player");
     let mut board = None;
     let mut size = 5;
+    // A command that arrived during a search and still needs handling
+    let mut pending = None;
     loop {
-        let message = tei_receiver.recv()?;
+        let message = match pending.take() {
+            Some(command) => command,
+            None => tei_receiver.recv()?,
+        };
         match message {
             TeiCommand::NewGame(s) => {
-                board = Some(State::new(s));
+                match rules::new_state(s) {
+                    Ok(state) => board = Some(state),
+                    Err(error) => {
+                        println!("info string error: {}", error);
+                        continue;
+                    }
+                }
                 size = s;
-                // Todo figure out if color matters
-                to_engine.send(Message::GameStart(Color::White)).unwrap();
             }
             TeiCommand::Go(s) => {
-                // Todo parse time
-                let go_state = board.take().unwrap();
-                to_engine.send(Message::MoveRequest(go_state)).unwrap();
-                let (_color, message) = from_engine.recv()?;
-
-                if let Message::MoveResponse(ply) = message {
-                    // println!("info {}", outcome);
-                    println!("bestmove {}", ply);
+                let go_state = match board.take() {
+                    Some(go_state) => go_state,
+                    None => {
+                        println!("info string error: go received before a position");
+                        continue;
+                    }
+                };
+                let limit = parse_go(&s, &go_state);
+                // When pondering, the position already includes the ply the opponent is expected
+                // to make.  The search runs until ponderhit puts it on the clock, or until it's
+                // stopped or a different position arrives.
+                let mut pondering = s.split_whitespace().any(|token| token == "ponder");
+                let request_limit = if pondering {
+                    SearchLimit::Infinite
                 } else {
-                    println!("Something went wrong, search failed!");
+                    limit.clone()
+                };
+                // The engine plays whichever side is to move in the position it's given
+                let engine_color = rules::side_to_move(&go_state);
+                if to_engine
+                    .send(Message::GameStart(engine_color))
+                    .and_then(|_| {
+                        to_engine.send(Message::MoveRequestLimited(go_state, request_limit))
+                    })
+                    .is_err()
+                {
+                    println!("info string error: engine is not running");
+                    continue;
+                }
+
+                // A response that came in while still pondering, held until ponderhit or stop
+                let mut held = None;
+                let mut stop_timer = None;
+                let mut discard = false;
+
+                // Keep listening to the GUI while the engine thinks, so it can be told to stop
+                let message = loop {
+                    if !pondering {
+                        if let Some(message) = held.take() {
+                            break message;
+                        }
+                    }
+
+                    match from_engine.recv_timeout(Duration::from_millis(10)) {
+                        Ok((_color, message)) => {
+                            if pondering {
+                                held = Some(message);
+                            } else {
+                                break message;
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => (),
+                        Err(RecvTimeoutError::Disconnected) => return Err(RecvError),
+                    }
+
+                    match tei_receiver.try_recv() {
+                        Ok(TeiCommand::Stop) => {
+                            pondering = false;
+                            to_engine.send(Message::StopSearch).ok();
+                        }
+                        Ok(TeiCommand::PonderHit) if pondering => {
+                            // The expected ply was played, so the search carries on as a normal
+                            // one.  Depth and infinite limits have no clock and run until stop.
+                            pondering = false;
+                            if let SearchLimit::Time(budget) = &limit {
+                                stop_timer = Some(stop_after(to_engine.clone(), *budget));
+                            }
+                        }
+                        Ok(TeiCommand::Position(position)) if pondering => {
+                            // The opponent played something else, so the pondering search is of
+                            // no use.  Its move is thrown away rather than reported.
+                            pondering = false;
+                            discard = true;
+                            to_engine.send(Message::StopSearch).ok();
+                            pending = Some(TeiCommand::Position(position));
+                        }
+                        Ok(TeiCommand::Quit) => return Ok(()),
+                        Ok(command) => println!("info string error: busy, ignoring {:?}", command),
+                        Err(TryRecvError::Empty) => (),
+                        Err(TryRecvError::Disconnected) => return Err(RecvError),
+                    }
+                };
+
+                // Dropping the timer cancels it, so it can't stop a later search
+                drop(stop_timer);
+                if discard {
+                    continue;
+                }
+
+                match message {
+                    Message::MoveResponseSearch(result) => {
+                        print_info(&result, "");
+                        print_bestmove(&result);
+                    }
+                    Message::MoveResponseMultiPv(ref lines) if !lines.is_empty() => {
+                        for (index, line) in lines.iter().enumerate() {
+                            print_info(line, &format!(" multipv {}", index + 1));
+                        }
+                        print_bestmove(&lines[0]);
+                    }
+                    Message::MoveResponse(ply) => println!("bestmove {}", ply.to_ptn()),
+                    _ => println!("info string error: search failed"),
                 }
             }
             TeiCommand::Position(s) => {
-                let mut side_to_move = Color::White;
-                let mut ply_count = 0;
-                let mut plies = Vec::new();
-                for m in s.split_whitespace() {
-                    // Swap colors in opening
-                    let color = if ply_count < 2 {
-                        side_to_move.flip()
-                    } else {
-                        side_to_move
-                    };
-                    if let Some(m) = Ply::from_ptn(m, color) {
-                        plies.push(m);
-                        ply_count += 1;
-                        side_to_move = side_to_move.flip();
+                let (start, move_tokens) = match parse_position(&s) {
+                    Ok(position) => position,
+                    Err(error) => {
+                        println!("info string error: {}", error);
+                        continue;
+                    }
+                };
+
+                // Only the standard opening swaps colors, not an arbitrary TPS start
+                let swap_opening = start.is_none();
+                let start = match start {
+                    Some(start) => start,
+                    None => match rules::new_state(size) {
+                        Ok(state) => state,
+                        Err(error) => {
+                            println!("info string error: {}", error);
+                            continue;
+                        }
+                    },
+                };
+                match ptn::play_moves(&start, &move_tokens, swap_opening) {
+                    Ok((state, plies)) => {
+                        if let Ok(history) = zobrist::position_history(&start, &plies) {
+                            if zobrist::is_repetition_draw(&history, zobrist::REPETITION_LIMIT) {
+                                println!("info string position is drawn by repetition");
+                            }
+                        }
+                        board = Some(state);
                     }
+                    Err((index, error)) => println!(
+                        "info string error: illegal position: ply {} ({}): {}",
+                        index + 1,
+                        move_tokens[index],
+                        error
+                    ),
                 }
-                board = Some(State::from_plies(size, &plies).expect("Could not parse ptn!"));
             }
+            TeiCommand::SetOption(name, value) => match (name.as_str(), value.parse()) {
+                ("HashSize", Ok(megabytes)) if megabytes > 0 && megabytes <= MAX_HASH_SIZE => {
+                    player.set_hash_size(megabytes)
+                }
+                ("Threads", Ok(threads)) if threads > 0 && threads <= MAX_THREADS => {
+                    player.set_threads(threads)
+                }
+                ("MultiPV", Ok(lines)) if lines > 0 && lines <= MAX_MULTIPV => {
+                    player.set_multipv(lines)
+                }
+                ("HashSize", _) | ("Threads", _) | ("MultiPV", _) => {
+                    println!("info string error: invalid value for {}: {}", name, value)
+                }
+                // Unknown options are ignored
+                _ => (),
+            },
+            // Nothing is being searched, so there's nothing to stop or confirm
+            TeiCommand::Stop | TeiCommand::PonderHit => (),
             TeiCommand::Quit => {
                 break;
             }
-            _ => println!("Unknown command: {:?}", message),
         }
     }
     Ok(())
 }
 
 pub fn identify() {
+    let defaults = SearchOptions::default();
     println!("id name Takkerus");
     println!("id author Chris Foster");
+    println!(
+        "option name HashSize type spin default {} min 1 max {}",
+        defaults.hash_size, MAX_HASH_SIZE
+    );
+    println!(
+        "option name Threads type spin default {} min 1 max {}",
+        defaults.threads, MAX_THREADS
+    );
+    println!(
+        "option name MultiPV type spin default {} min 1 max {}",
+        defaults.multipv, MAX_MULTIPV
+    );
     println!("teiok");
 }
 
@@ -153,13 +341,23 @@ pub fn tei_loop(sender: Sender<TeiCommand>) {
                 break;
             } else if line == "stop" {
                 sender.send(TeiCommand::Stop).unwrap();
+            } else if line == "ponderhit" {
+                sender.send(TeiCommand::PonderHit).unwrap();
             } else if line.starts_with("position") {
                 sender.send(TeiCommand::Position(line.to_string())).unwrap();
             } else if line.starts_with("go") {
                 sender.send(TeiCommand::Go(line.to_string())).unwrap();
+            } else if line.starts_with("setoption") {
+                if let Some((name, value)) = parse_setoption(line) {
+                    sender.send(TeiCommand::SetOption(name, value)).unwrap();
+                } else {
+                    println!("info string error: malformed setoption: {}", line);
+                }
             } else if line.starts_with("teinewgame") {
-                let size = line.split_whitespace().find_map(|x| x.parse().ok());
-                sender.send(TeiCommand::NewGame(size.unwrap())).unwrap();
+                match line.split_whitespace().find_map(|x| x.parse().ok()) {
+                    Some(size) => sender.send(TeiCommand::NewGame(size)).unwrap(),
+                    None => println!("info string error: teinewgame requires a board size"),
+                }
             } else {
                 println!("Unknown Tei Command: {}", buffer);
             }
@@ -167,3 +365,137 @@ pub fn tei_loop(sender: Sender<TeiCommand>) {
         }
     });
 }
+
+// Splits "position startpos [moves ...]" or "position tps <board> <side> <move> [moves ...]" into
+// the TPS start, if there is one, and the moves to play from it
+fn parse_position(line: &str) -> Result<(Option<State>, Vec<&str>), String> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+
+    let (start, rest) = match tokens.get(1) {
+        Some(&"startpos") => (None, &tokens[2..]),
+        Some(&"tps") => {
+            // A TPS string spans three tokens: board, side to move, and move number
+            if tokens.len() < 5 {
+                return Err(String::from(
+                    "tps needs a board, side to move, and move number",
+                ));
+            }
+            (Some(tps::from_tps(&tokens[2..5].join(" "))?), &tokens[5..])
+        }
+        Some(token) => return Err(format!("expected startpos or tps, found \"{}\"", token)),
+        None => return Err(String::from("position needs startpos or tps")),
+    };
+
+    match rest.split_first() {
+        None => Ok((start, Vec::new())),
+        Some((&"moves", moves)) => Ok((start, moves.to_vec())),
+        Some((token, _)) => Err(format!("expected moves, found \"{}\"", token)),
+    }
+}
+
+// multipv is empty, or " multipv <n>" for one of several lines
+fn print_info(result: &SearchResult, multipv: &str) {
+    let pv_ptn = result
+        .principal_variation
+        .iter()
+        .map(|ply| ply.to_ptn())
+        .collect::<Vec<_>>();
+    let node_info = match (result.nodes, result.nodes_per_second()) {
+        (Some(nodes), Some(nps)) => format!(" nodes {} nps {}", nodes, nps),
+        _ => String::new(),
+    };
+    let bound = match result.bound {
+        Bound::Exact => "",
+        Bound::Lower => " lowerbound",
+        Bound::Upper => " upperbound",
+    };
+    println!(
+        "info depth {}{} score {}{} time {}{} pv {}",
+        result.depth,
+        multipv,
+        score_text(result.score),
+        bound,
+        result.elapsed.as_millis(),
+        node_info,
+        pv_ptn.join(" "),
+    );
+}
+
+// A forced road is reported in moves to the road, negative when it's the opponent's, and anything
+// else in centi-flats
+fn score_text(score: i32) -> String {
+    match plies_to_road(score) {
+        Some(plies) if plies > 0 => format!("mate {}", (plies + 1) / 2),
+        Some(plies) => format!("mate {}", plies / 2),
+        None => format!("cp {}", score),
+    }
+}
+
+fn print_bestmove(result: &SearchResult) {
+    // The second ply of the variation is the reply we expect, to ponder on
+    let ponder = match result.principal_variation.get(1) {
+        Some(ply) => format!(" ponder {}", ply.to_ptn()),
+        None => String::new(),
+    };
+    println!("bestmove {}{}", result.best_ply.to_ptn(), ponder);
+}
+
+// Stops the engine's search once the budget has passed, unless the returned sender is dropped first
+fn stop_after(to_engine: Sender<Message>, budget: Duration) -> Sender<()> {
+    let (cancel, cancelled) = channel();
+    thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(budget) {
+            to_engine.send(Message::StopSearch).ok();
+        }
+    });
+    cancel
+}
+
+// Splits "setoption name <name> value <value>" into its name and value; both may contain spaces
+fn parse_setoption(line: &str) -> Option<(String, String)> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let name_start = tokens.iter().position(|&token| token == "name")? + 1;
+    let value_start = tokens
+        .iter()
+        .position(|&token| token == "value")
+        .unwrap_or(tokens.len());
+
+    if name_start >= value_start {
+        return None;
+    }
+
+    Some((
+        tokens[name_start..value_start].join(" "),
+        tokens[(value_start + 1).min(tokens.len())..].join(" "),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::ROAD_WIN;
+
+    #[test]
+    fn roads_are_reported_in_moves() {
+        assert_eq!(score_text(ROAD_WIN - 1), "mate 1");
+        assert_eq!(score_text(ROAD_WIN - 3), "mate 2");
+        assert_eq!(score_text(-(ROAD_WIN - 2)), "mate -1");
+        assert_eq!(score_text(-(ROAD_WIN - 4)), "mate -2");
+    }
+
+    #[test]
+    fn other_scores_are_reported_in_centi_flats() {
+        assert_eq!(score_text(0), "cp 0");
+        assert_eq!(score_text(-250), "cp -250");
+    }
+
+    #[test]
+    fn go_nodes_limits_the_nodes() {
+        let state = rules::new_state(5).unwrap();
+
+        match parse_go("go nodes 5000", &state) {
+            SearchLimit::Nodes(nodes) => assert_eq!(nodes, 5000),
+            limit => panic!("expected a node limit, got {:?}", limit),
+        }
+    }
+}
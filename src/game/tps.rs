@@ -0,0 +1,261 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::str::FromStr;
+
+use zero_sum::impls::tak::{Color, Piece, State};
+
+use super::rules::{self, piece_color};
+
+// Serializes a state into the bare TPS string, i.e. without the surrounding [TPS "..."] tag
+pub fn to_tps(state: &State) -> String {
+    let board_size = state.board.len();
+
+    let mut rows = Vec::with_capacity(board_size);
+
+    // TPS lists rows from the top of the board down
+    for y in (0..board_size).rev() {
+        let mut squares = Vec::with_capacity(board_size);
+        let mut empty_run = 0;
+
+        for x in 0..board_size {
+            let stack = &state.board[x][y];
+
+            if stack.is_empty() {
+                empty_run += 1;
+                continue;
+            }
+
+            if empty_run > 0 {
+                squares.push(empty_squares(empty_run));
+                empty_run = 0;
+            }
+
+            // Stacks are stored bottom-first, which is also the order TPS expects
+            let mut square = String::with_capacity(stack.len() + 1);
+            for piece in stack {
+                square.push(color_digit(piece_color(piece)));
+            }
+
+            match stack.last() {
+                Some(&Piece::StandingStone(_)) => square.push('S'),
+                Some(&Piece::Capstone(_)) => square.push('C'),
+                _ => (),
+            }
+
+            squares.push(square);
+        }
+
+        if empty_run > 0 {
+            squares.push(empty_squares(empty_run));
+        }
+
+        rows.push(squares.join(","));
+    }
+
+    format!(
+        "{} {} {}",
+        rows.join("/"),
+        if state.ply_count % 2 == 0 { '1' } else { '2' },
+        state.ply_count / 2 + 1,
+    )
+}
+
+// Parses a bare TPS string, explaining what's wrong with it instead of just failing
+pub fn from_tps(tps: &str) -> Result<State, String> {
+    let fields = tps.split_whitespace().collect::<Vec<_>>();
+
+    if fields.len() != 3 {
+        return Err(String::from(
+            "TPS must contain a board, a side to move, and a move number.",
+        ));
+    }
+
+    let rows = fields[0].split('/').collect::<Vec<_>>();
+    let board_size = rows.len();
+
+    let (flatstone_reserve, capstone_reserve) = match rules::starting_reserves(board_size) {
+        Some(reserves) => reserves,
+        None => return Err(format!("Invalid board size: {}.", board_size)),
+    };
+
+    // Indexed by color; standing stones are drawn from the flatstone reserve
+    let mut flatstones = [0; 2];
+    let mut capstones = [0; 2];
+
+    for (row_index, row) in rows.iter().enumerate() {
+        let mut columns = 0;
+
+        for square in row.split(',') {
+            if square.starts_with('x') {
+                columns += if square.len() == 1 {
+                    1
+                } else if let Ok(run) = usize::from_str(&square[1..]) {
+                    run
+                } else {
+                    return Err(format!("Invalid empty square count: \"{}\".", square));
+                };
+
+                continue;
+            }
+
+            if square.is_empty() {
+                return Err(format!(
+                    "Row {} contains an empty square entry.",
+                    board_size - row_index
+                ));
+            }
+
+            columns += 1;
+
+            let mut chars = square.chars().peekable();
+            while let Some(c) = chars.next() {
+                let color = match c {
+                    '1' => 0,
+                    '2' => 1,
+                    _ => {
+                        return Err(format!(
+                            "Unrecognized character '{}' in square \"{}\".",
+                            c, square
+                        ))
+                    }
+                };
+
+                match chars.peek() {
+                    Some(&'C') => {
+                        capstones[color] += 1;
+                        chars.next();
+                    }
+                    Some(&'S') => {
+                        flatstones[color] += 1;
+                        chars.next();
+                    }
+                    _ => {
+                        flatstones[color] += 1;
+                        continue;
+                    }
+                }
+
+                // Standing stones and capstones can only top a stack
+                if chars.peek().is_some() {
+                    return Err(format!(
+                        "Square \"{}\" is covered above a standing stone or capstone.",
+                        square
+                    ));
+                }
+            }
+        }
+
+        if columns != board_size {
+            return Err(format!(
+                "Row {} has {} squares, but the board is {}x{}.",
+                board_size - row_index,
+                columns,
+                board_size,
+                board_size,
+            ));
+        }
+    }
+
+    for (color, name) in ["White", "Black"].iter().enumerate() {
+        if flatstones[color] > flatstone_reserve {
+            return Err(format!(
+                "{} has {} flatstones on the board, but only {} are available.",
+                name, flatstones[color], flatstone_reserve,
+            ));
+        }
+
+        if capstones[color] > capstone_reserve {
+            return Err(format!(
+                "{} has {} capstones on the board, but only {} are available.",
+                name, capstones[color], capstone_reserve,
+            ));
+        }
+    }
+
+    if fields[1] != "1" && fields[1] != "2" {
+        return Err(format!("Invalid side to move: \"{}\".", fields[1]));
+    }
+
+    match usize::from_str(fields[2]) {
+        Ok(move_number) if move_number >= 1 => (),
+        _ => return Err(format!("Invalid move number: \"{}\".", fields[2])),
+    }
+
+    match State::from_tps(&format!("[TPS \"{}\"]", tps)) {
+        Some(state) => Ok(state),
+        None => Err(String::from("Invalid TPS.")),
+    }
+}
+
+// A copy of the state with zero_sum's analysis rebuilt from the board alone, by way of TPS.  Use it
+// after editing a state's board directly, since the analysis is otherwise only updated
+// incrementally by execute_ply.
+pub fn rebuild_analysis(state: &State) -> Result<State, String> {
+    from_tps(&to_tps(state))
+}
+
+fn empty_squares(count: usize) -> String {
+    if count == 1 {
+        String::from("x")
+    } else {
+        format!("x{}", count)
+    }
+}
+
+fn color_digit(color: Color) -> char {
+    match color {
+        Color::White => '1',
+        Color::Black => '2',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trips(tps: &str) {
+        assert_eq!(to_tps(&from_tps(tps).unwrap()), tps);
+    }
+
+    #[test]
+    fn the_start_position_round_trips() {
+        let start = "x5/x5/x5/x5/x5 1 1";
+
+        assert_round_trips(start);
+        assert_eq!(to_tps(&rules::new_state(5).unwrap()), start);
+    }
+
+    #[test]
+    fn stacks_round_trip() {
+        assert_round_trips("x5/x,1212,x3/x5/x2,21,x2/2,x3,1 1 9");
+    }
+
+    #[test]
+    fn standing_stones_and_capstones_round_trip() {
+        assert_round_trips("x4,1C/x,2S,x3/x5/x2,12S,x2/2C,x3,1 1 8");
+        assert_round_trips("x6/x6/x2,21C,x3/x6/1S,x5/x6 2 4");
+    }
+
+    #[test]
+    fn the_side_to_move_round_trips() {
+        assert_round_trips("1,1,x3/x5/x5/x5/2,x4 1 3");
+        assert_round_trips("1,1,x3/x5/x2,2,x2/x5/2,x4 2 3");
+    }
+}
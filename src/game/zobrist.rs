@@ -0,0 +1,239 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::hash::{Hash, Hasher};
+
+use zero_sum::impls::tak::{Color, Piece, Ply, State};
+
+use super::{rules, symmetry};
+use crate::player::{SplitMix64, GOLDEN_GAMMA};
+
+const SIDE_TO_MOVE_KEY: u64 = GOLDEN_GAMMA;
+
+// How many times a position must occur before the game is drawn by repetition
+pub const REPETITION_LIMIT: usize = 3;
+
+// Hashes the position: every piece by square, height within its stack, and type and color,
+// plus the side to move.  Identical positions hash equal regardless of the move order that
+// reached them.
+pub fn zobrist_hash(state: &State) -> u64 {
+    if state.ply_count % 2 == 1 {
+        board_hash(state) ^ SIDE_TO_MOVE_KEY
+    } else {
+        board_hash(state)
+    }
+}
+
+// zobrist_hash without the side to move, so it's the same whoever is to play
+pub fn board_hash(state: &State) -> u64 {
+    let board_size = state.board.len();
+    let mut hash = 0;
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            hash ^= stack_change(x, y, &[], &state.board[x][y]);
+        }
+    }
+
+    hash
+}
+
+// The zobrist_hash of the position after the ply, updated from the hash of the position before it
+// by rehashing only the squares the ply changed.  after must be before with the ply executed.
+pub fn hash_after_ply(hash: u64, before: &State, after: &State, ply: &Ply) -> u64 {
+    let mut squares = rules::covered_squares(ply);
+    if let Ply::Slide { x, y, .. } = *ply {
+        squares.push((x, y));
+    }
+
+    squares
+        .into_iter()
+        .fold(hash ^ SIDE_TO_MOVE_KEY, |hash, (x, y)| {
+            hash ^ stack_change(x, y, &before.board[x][y], &after.board[x][y])
+        })
+}
+
+// The keys of the pieces that differ between two stacks on the same square.  The pieces they share
+// at the bottom hash the same in both, so they're skipped.
+fn stack_change(x: usize, y: usize, before: &[Piece], after: &[Piece]) -> u64 {
+    let shared = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|&(before, after)| before == after)
+        .count();
+
+    let keys = |stack: &[Piece]| {
+        stack
+            .iter()
+            .enumerate()
+            .skip(shared)
+            .fold(0, |hash, (height, piece)| {
+                hash ^ piece_key(x * 8 + y, height, piece)
+            })
+    };
+
+    keys(before) ^ keys(after)
+}
+
+// The smallest zobrist_hash over the board's eight orientations, so that positions that are
+// rotations or reflections of one another hash equal.  Includes the side to move.
+pub fn canonical_hash(state: &State) -> u64 {
+    symmetry::symmetries(state)
+        .iter()
+        .map(zobrist_hash)
+        .min()
+        .unwrap()
+}
+
+// canonical_hash, but ignoring the side to move
+pub fn canonical_board_hash(state: &State) -> u64 {
+    symmetry::symmetries(state)
+        .iter()
+        .map(board_hash)
+        .min()
+        .unwrap()
+}
+
+// The hash of the starting position and of the position after each ply
+pub fn position_history(start: &State, plies: &[Ply]) -> Result<Vec<u64>, String> {
+    let mut state = start.clone();
+    let mut history = vec![zobrist_hash(&state)];
+
+    for ply in plies {
+        rules::execute_validated(&mut state, ply)?;
+        history.push(zobrist_hash(&state));
+    }
+
+    Ok(history)
+}
+
+// Whether the last position in the history, i.e. the current one, has occurred at least limit
+// times, counting itself
+pub fn is_repetition_draw(history: &[u64], limit: usize) -> bool {
+    match history.last() {
+        Some(current) => history.iter().filter(|&hash| hash == current).count() >= limit,
+        None => false,
+    }
+}
+
+// Stands in for a table of random keys; stacks can grow past any reasonable table height,
+// so the keys are derived by scrambling the table index instead
+fn piece_key(square: usize, height: usize, piece: &Piece) -> u64 {
+    let piece_index = match *piece {
+        Piece::Flatstone(Color::White) => 0,
+        Piece::StandingStone(Color::White) => 1,
+        Piece::Capstone(Color::White) => 2,
+        Piece::Flatstone(Color::Black) => 3,
+        Piece::StandingStone(Color::Black) => 4,
+        Piece::Capstone(Color::Black) => 5,
+    };
+
+    scramble(((height * 64 + square) * 6 + piece_index) as u64 + 1)
+}
+
+// Spreads the index over the word before SplitMix64's finalizer, so nearby indices get unrelated
+// keys
+fn scramble(z: u64) -> u64 {
+    SplitMix64::mix(z.wrapping_mul(GOLDEN_GAMMA))
+}
+
+// A state for use as a HashMap key, e.g. in a memoization cache.  Keys are equal when their boards
+// and ply counts are, however the positions were reached; zero_sum's analysis is ignored, as in
+// rules::board_eq.  A transposition table should store zobrist_hash instead, which is far cheaper
+// to compare; this is for convenience.
+#[derive(Clone, Debug)]
+pub struct StateKey(pub State);
+
+impl PartialEq for StateKey {
+    fn eq(&self, other: &StateKey) -> bool {
+        rules::board_eq(&self.0, &other.0) && self.0.ply_count == other.0.ply_count
+    }
+}
+
+impl Eq for StateKey {}
+
+impl Hash for StateKey {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        board_hash(&self.0).hash(hasher);
+        self.0.ply_count.hash(hasher);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ptn, self_play};
+    use std::collections::HashMap;
+
+    #[test]
+    fn transposed_move_orders_hash_equal() {
+        let start = rules::new_state(5).unwrap();
+        let (first, _) = ptn::play_moves(&start, &["a1", "e5", "c3", "c2", "d3"], true).unwrap();
+        let (second, _) = ptn::play_moves(&start, &["a1", "e5", "d3", "c2", "c3"], true).unwrap();
+        let (other, _) = ptn::play_moves(&start, &["a1", "e5", "d3", "c2", "b3"], true).unwrap();
+
+        assert_eq!(zobrist_hash(&first), zobrist_hash(&second));
+        assert_ne!(zobrist_hash(&first), zobrist_hash(&other));
+    }
+
+    #[test]
+    fn reflected_positions_have_equal_canonical_hashes() {
+        let start = rules::new_state(5).unwrap();
+        let (state, _) = ptn::play_moves(&start, &["a1", "e5", "b2", "d1", "Sc4"], true).unwrap();
+        // The same moves with the files mirrored
+        let (reflected, _) =
+            ptn::play_moves(&start, &["e1", "a5", "d2", "b1", "Sc4"], true).unwrap();
+
+        assert_ne!(zobrist_hash(&state), zobrist_hash(&reflected));
+        assert_eq!(canonical_hash(&state), canonical_hash(&reflected));
+        assert_eq!(
+            canonical_hash(&state),
+            canonical_hash(&symmetry::rotate_90(&state))
+        );
+    }
+
+    #[test]
+    fn incremental_hash_matches_a_full_recompute() {
+        for size in 3..=8 {
+            for seed in 0..4 {
+                let (states, plies, _) = self_play::play_random_game(size, seed).unwrap();
+                let mut hash = zobrist_hash(&states[0]);
+
+                for (index, ply) in plies.iter().enumerate() {
+                    hash = hash_after_ply(hash, &states[index], &states[index + 1], ply);
+                    assert_eq!(hash, zobrist_hash(&states[index + 1]), "{}", ply.to_ptn());
+                }
+            }
+        }
+    }
+    #[test]
+    fn transposed_move_orders_share_a_state_key() {
+        let start = rules::new_state(5).unwrap();
+        let (first, _) = ptn::play_moves(&start, &["a1", "e5", "c3", "c2", "d3"], true).unwrap();
+        let (second, _) = ptn::play_moves(&start, &["a1", "e5", "d3", "c2", "c3"], true).unwrap();
+
+        let mut visits = HashMap::new();
+        for state in vec![first, second] {
+            *visits.entry(StateKey(state)).or_insert(0) += 1;
+        }
+
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits.values().next(), Some(&2));
+    }
+}
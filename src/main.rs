@@ -28,11 +28,11 @@ use std::env;
 use std::str::FromStr;
 
 use zero_sum::analysis::search::{PvSearch, Search};
-use zero_sum::impls::tak::evaluator::StaticEvaluator;
 use zero_sum::impls::tak::*;
 
 use arguments::{parse_player, Options};
-use game::{logger, Game};
+use game::{logger, rules, Game};
+use player::WeightedEvaluator;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -47,6 +47,7 @@ fn main() {
         println!("A playable tak board.  Supports play between any combination of humans and AIs, and can act as a PlayTak.com client.");
         println!("  Commands:");
         println!("    analyze    Use an AI to analyze a board.");
+        println!("    perft      Count the positions a few plies ahead, to check and time move generation.");
         println!(
             "    play       Start a game between any combination of humans and AIs. (default)"
         );
@@ -71,26 +72,32 @@ fn main() {
         analyze_options
             .flag("h", "help")
             .opt("f", "file")
+            .opt("t", "tps")
             .opt("s", "size")
             .opt("a", "ai");
 
         let mut matches = analyze_options.parse(&matches.free[1..]);
 
         if matches.opt_present("help") {
-            println!("Usage:\n  takkerus analyze [-f file | -s int] [-a string [AI options]]\n");
+            println!("Usage:\n  takkerus analyze [-f file | -t string | -s int] [-a string [AI options]]\n");
             println!("Analyzes a board in TPS format or a blank board of the specified size, using the specified AI.");
             println!("    -f, --file  FILE     Specifies a PTN file.");
+            println!("    -t, --tps   STRING   Specifies a board in TPS, or a PTN game.");
             println!("    -s, --size  INT      Specifies a blank board of Size. (default 5)");
             println!("    -a, --ai    STRING   The type of AI to use.  Options are:");
             println!("                           pvsearch (default)");
             println!("  PVSearch options:");
-            println!("    -d, --depth INT      The depth of the search.");
+            println!("    -d, --depth INT      The depth of the search.  Prints the best move, score, and principal variation, one per line.");
             println!("    -g, --goal  INT      The maximum search time in seconds. (default 60)");
             return;
         }
 
-        if matches.opt_present("file") && matches.opt_present("size") {
-            println!("  Error: Both file and size were specified.");
+        let sources = ["file", "tps", "size"]
+            .iter()
+            .filter(|&&source| matches.opt_present(source))
+            .count();
+        if sources > 1 {
+            println!("  Error: Only one of file, tps, and size may be specified.");
             return;
         }
 
@@ -108,13 +115,22 @@ fn main() {
                     return;
                 }
             }
+        } else if let Some(position) = matches.opt_str("tps") {
+            match game::analyze::parse_position(&position) {
+                Ok(state) => state,
+                Err(error) => {
+                    println!("  Error: {}", error);
+                    return;
+                }
+            }
         } else if let Some(size) = matches.opt_str("size") {
             if let Ok(size) = usize::from_str(&size) {
-                if size >= 3 && size <= 8 {
-                    State::new(size)
-                } else {
-                    println!("  Error: Invalid size.");
-                    return;
+                match rules::new_state(size) {
+                    Ok(state) => state,
+                    Err(error) => {
+                        println!("  Error: {}", error);
+                        return;
+                    }
                 }
             } else {
                 println!("  Error: Invalid size.");
@@ -124,6 +140,8 @@ fn main() {
             State::new(5)
         };
 
+        let mut fixed_depth = None;
+
         let mut search: Box<dyn Search<State>> = if let Some(search) = matches.opt_str("ai") {
             if search == "pvsearch" {
                 let mut pvsearch_options = Options::new();
@@ -143,27 +161,32 @@ fn main() {
 
                 if let Some(depth) = matches.opt_str("depth") {
                     if let Ok(depth) = u8::from_str(&depth) {
-                        Box::new(PvSearch::with_depth(StaticEvaluator, depth))
+                        fixed_depth = Some(depth);
+                        Box::new(PvSearch::with_depth(WeightedEvaluator::default(), depth))
                     } else {
                         println!("  Error: Invalid depth.");
                         return;
                     }
                 } else if let Some(goal) = matches.opt_str("goal") {
                     if let Ok(goal) = u16::from_str(&goal) {
-                        Box::new(PvSearch::with_goal(StaticEvaluator, goal, 12.0))
+                        Box::new(PvSearch::with_goal(
+                            WeightedEvaluator::default(),
+                            goal,
+                            12.0,
+                        ))
                     } else {
                         println!("  Error: Invalid goal.");
                         return;
                     }
                 } else {
-                    Box::new(PvSearch::with_goal(StaticEvaluator, 60, 12.0))
+                    Box::new(PvSearch::with_goal(WeightedEvaluator::default(), 60, 12.0))
                 }
             } else {
                 println!("  Error: Unrecognized AI type: {}.", search);
                 return;
             }
         } else {
-            Box::new(PvSearch::with_goal(StaticEvaluator, 60, 12.0))
+            Box::new(PvSearch::with_goal(WeightedEvaluator::default(), 60, 12.0))
         };
 
         if !matches.free.is_empty() {
@@ -171,28 +194,43 @@ fn main() {
             return;
         }
 
+        // A fixed-depth analysis is printed in a form meant for scripts
+        if let Some(depth) = fixed_depth {
+            match game::analyze::analyze(&state, depth) {
+                Ok(result) => game::analyze::print_analysis(&result),
+                Err(error) => println!("  Error: {}", error),
+            }
+            return;
+        }
+
         println!("Analyzing state...");
         println!("Analysis:\n{}", search.search(&state, None));
     } else if !matches.free.is_empty() && matches.free[0] == "play" {
         let mut play_options = arguments::Options::new();
-        play_options.flag("h", "help").opt("s", "size");
+        play_options
+            .flag("h", "help")
+            .opt("s", "size")
+            .opt("k", "komi");
 
         let mut matches = play_options.parse(&matches.free[1..]);
 
         if matches.opt_present("help") {
             println!(
-                "Usage:\n  takkerus play [-s int] [-p1 string [Options]] [-p2 string [Options]]\n"
+                "Usage:\n  takkerus play [-s int] [-k float] [-p1 string [Options]] [-p2 string [Options]]\n"
             );
             println!("Starts a game of Tak between any combination of humans and AIs.");
             println!("    -s, --size  INT      Specifies a blank board of Size. (default 5)");
+            println!("    -k, --komi  FLOAT    Flats added to Black's count in a flat win, in steps of 0.5. (default 0)");
             println!("        --p1    STRING   The type of player 1. Options are:");
             println!("                           human    (default)");
             println!("                           pvsearch");
             println!("                           playtak");
+            println!("                           random");
             println!("        --p2    STRING   The type of player 2. Options are:");
             println!("                           human");
             println!("                           pvsearch (default)");
             println!("                           playtak");
+            println!("                           random");
             println!("\n  Human options:");
             println!("    -n, --name  STRING   The name of the player to record. (default Human)");
             println!("\n  PVSearch options:");
@@ -200,6 +238,9 @@ fn main() {
             println!(
                 "    -g, --goal  INT      The number of seconds per move to aim for. (default 60)"
             );
+            println!("    -b, --book  FILE     A PTN file of games to play the openings of.");
+            println!("\n  Random options:");
+            println!("    -r, --seed  INT      Seeds the random moves, to replay a game. (default from the clock)");
             println!("\n  PlayTak options:");
             println!(
                 "    -h, --host  STRING   The host to connect to. (default \"playtak.com:10000\")"
@@ -227,7 +268,7 @@ fn main() {
 
         if let Some(size) = matches.opt_str("size") {
             if let Ok(size) = usize::from_str(&size) {
-                if size >= 3 && size <= 8 {
+                if rules::starting_reserves(size).is_some() {
                     game.header.size = size;
                 } else {
                     println!("  Error: Invalid size.");
@@ -239,6 +280,15 @@ fn main() {
             }
         }
 
+        if let Some(komi) = matches.opt_str("komi") {
+            if let Some(komi) = game::Header::parse_komi(&komi) {
+                game.header.komi = komi;
+            } else {
+                println!("  Error: Invalid komi.");
+                return;
+            }
+        }
+
         let mut p1_options = Options::new();
         p1_options.opt("", "p1");
 
@@ -308,6 +358,78 @@ fn main() {
         game.add_player(p2).ok();
 
         game.play().ok();
+    } else if matches.free[0] == "perft" {
+        let mut perft_options = Options::new();
+        perft_options
+            .flag("h", "help")
+            .flag("D", "divide")
+            .opt("t", "tps")
+            .opt("s", "size")
+            .opt("d", "depth");
+
+        let matches = perft_options.parse(&matches.free[1..]);
+
+        if matches.opt_present("help") {
+            println!("Usage:\n  takkerus perft [-t string | -s int] -d int [-D]\n");
+            println!("Counts the positions reachable in Depth plies, and how quickly they were generated, to check and benchmark move generation.");
+            println!("    -t, --tps    STRING   Specifies a board in TPS.");
+            println!("    -s, --size   INT      Specifies a blank board of Size. (default 5)");
+            println!("    -d, --depth  INT      The number of plies to count to.");
+            println!(
+                "    -D, --divide          Also prints the count beneath each ply from the board."
+            );
+            return;
+        }
+
+        if !matches.free.is_empty() {
+            println!("  Error: Unrecognized option: \"{}\".", matches.free[0]);
+            return;
+        }
+
+        let state = if let Some(position) = matches.opt_str("tps") {
+            game::tps::from_tps(&position)
+        } else {
+            match matches.opt_str("size").map(|size| usize::from_str(&size)) {
+                Some(Ok(size)) => rules::new_state(size),
+                Some(Err(_)) => Err(String::from("Invalid size.")),
+                None => rules::new_state(5),
+            }
+        };
+        let state = match state {
+            Ok(state) => state,
+            Err(error) => {
+                println!("  Error: {}", error);
+                return;
+            }
+        };
+
+        let depth = match matches
+            .opt_str("depth")
+            .map(|depth| usize::from_str(&depth))
+        {
+            Some(Ok(depth)) => depth,
+            _ => {
+                println!("  Error: Invalid or missing depth.");
+                return;
+            }
+        };
+
+        let start = std::time::Instant::now();
+        let count = if matches.opt_present("divide") {
+            game::movegen::perft_divide(&state, depth)
+        } else {
+            game::movegen::perft(&state, depth)
+        };
+        let elapsed = start.elapsed();
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        println!(
+            "Depth {}: {} positions in {:.3} seconds ({:.0} per second)",
+            depth,
+            count,
+            seconds,
+            count as f64 / seconds.max(0.000_001),
+        );
     } else if matches.free[0] == "tei" {
         let (s, r) = std::sync::mpsc::channel();
         tei_loop(s);
@@ -25,9 +25,8 @@ use std::thread;
 use std::time::Duration;
 
 use zero_sum::impls::tak::{Color, Piece, Ply, State};
-use zero_sum::State as StateTrait;
 
-use crate::game::Message;
+use crate::game::{ptn, rules, Message};
 use crate::player::Player;
 
 pub struct CliPlayer {
@@ -329,18 +328,13 @@ fn parse_input(
 }
 
 fn parse_ply(string: &str, state: &State) -> Option<Ply> {
-    let mut state = state.clone();
     let board_size = state.board.len();
 
-    let player_color = if state.ply_count % 2 == 0 {
-        Color::White
-    } else {
-        Color::Black
-    };
+    let player_color = rules::side_to_move(state);
 
-    let ply = match Ply::from_ptn(string, player_color) {
+    let ply = match ptn::parse_ptn(string, player_color) {
         // XXX Move this error checking into State?
-        Some(mut ply) => {
+        Ok(mut ply) => {
             if state.ply_count < 2 {
                 ply = match ply {
                     Ply::Place {
@@ -370,13 +364,13 @@ fn parse_ply(string: &str, state: &State) -> Option<Ply> {
 
             ply
         }
-        None => {
-            println!("  Invalid entry.");
+        Err(error) => {
+            println!("  Invalid entry: {}.", error);
             return None;
         }
     };
 
-    if let Err(error) = state.execute_ply(Some(&ply)) {
+    if let Err(error) = rules::validate_ply(state, &ply) {
         println!("  {}", error);
         None
     } else {
@@ -0,0 +1,260 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use zero_sum::analysis::{self, Evaluator};
+use zero_sum::impls::tak::{Color, Piece, Resolution, State};
+use zero_sum::prepare_evaluation_tuple;
+use zero_sum::State as StateTrait;
+
+use crate::game::{resolution, rules};
+use crate::player::{plies_to_road, FLAT_WIN, ROAD_WIN};
+
+// What each feature of a position is worth to WeightedEvaluator, in centi-flats.  Every term is
+// counted for both players, and the score is the side to move's total less the opponent's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalWeights {
+    // Per stack the player controls, by the piece on top
+    pub flatstone: i32,
+    pub standing_stone: i32,
+    pub capstone: i32,
+    // Per stone of the player's own beneath the top of a stack it controls
+    pub captive: i32,
+    // Per stone of the player's buried in a stack the opponent controls
+    pub covered: i32,
+    // Per road group, by the number of rows or columns it spans, whichever is more
+    pub group_span: [i32; 9],
+    // Per stone left in the player's reserve
+    pub reserve: i32,
+}
+
+impl Default for EvalWeights {
+    fn default() -> EvalWeights {
+        EvalWeights {
+            flatstone: 100,
+            standing_stone: 55,
+            capstone: 80,
+            captive: 35,
+            covered: -20,
+            group_span: [0, 0, 20, 50, 100, 175, 275, 400, 550],
+            reserve: 5,
+        }
+    }
+}
+
+// A WeightedEvaluator score: centi-flats for the side to move, with finished games scored as
+// negamax_search scores them.  A draw is exactly 0.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Evaluation(pub i32);
+
+prepare_evaluation_tuple!(Evaluation);
+
+impl analysis::Evaluation for Evaluation {
+    fn null() -> Evaluation {
+        Evaluation(0)
+    }
+
+    fn shift(self, steps: i32) -> Evaluation {
+        Evaluation(self.0 + steps)
+    }
+
+    fn win() -> Evaluation {
+        Evaluation(ROAD_WIN)
+    }
+
+    fn max() -> Evaluation {
+        Evaluation(i32::max_value())
+    }
+
+    // Flat wins are wins too
+    fn is_win(&self) -> bool {
+        self.0 > FLAT_WIN / 2
+    }
+}
+
+// An Evaluator for zero_sum's searches whose terms can be tuned, unlike StaticEvaluator's
+#[derive(Clone, Debug, Default)]
+pub struct WeightedEvaluator {
+    pub weights: EvalWeights,
+}
+
+impl WeightedEvaluator {
+    pub fn new(weights: EvalWeights) -> WeightedEvaluator {
+        WeightedEvaluator { weights: weights }
+    }
+}
+
+impl Evaluator for WeightedEvaluator {
+    type State = State;
+    type Evaluation = Evaluation;
+
+    // The evaluator can't know how far a finished game is from the search's root, so a road is
+    // marked down by the ply count instead.  A quicker road still scores higher.
+    fn evaluate(&self, state: &State) -> Evaluation {
+        let (winner, score) = match state.check_resolution() {
+            None => return Evaluation(evaluate(state, &self.weights)),
+            Some(Resolution::Draw) => return Evaluation(0),
+            Some(Resolution::Road(color)) => (color, ROAD_WIN - state.ply_count as i32),
+            Some(Resolution::Flat(color)) => (
+                color,
+                FLAT_WIN + resolution::flat_differential(state).abs() * 100,
+            ),
+        };
+
+        if winner == rules::side_to_move(state) {
+            Evaluation(score)
+        } else {
+            Evaluation(-score)
+        }
+    }
+}
+
+// A search's evaluation in negamax_search's centi-flats, where a road is marked down by the plies
+// from the search's root to the road rather than by the road's ply count
+pub fn score_from_root(evaluation: Evaluation, root: &State) -> i32 {
+    let root_plies = root.ply_count as i32;
+
+    match plies_to_road(evaluation.0) {
+        Some(plies) if plies > 0 => ROAD_WIN - (plies - root_plies),
+        Some(plies) => -(ROAD_WIN - (-plies - root_plies)),
+        None => evaluation.0,
+    }
+}
+
+// The weighted score of a position that isn't over, for the side to move
+pub fn evaluate(state: &State, weights: &EvalWeights) -> i32 {
+    let side_to_move = rules::side_to_move(state);
+    player_total(state, side_to_move, weights) - player_total(state, side_to_move.flip(), weights)
+}
+
+fn player_total(state: &State, color: Color, weights: &EvalWeights) -> i32 {
+    let mut total = 0;
+
+    for stack in state.board.iter().flat_map(|column| column.iter()) {
+        let (top, beneath) = match stack.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+
+        let own_beneath = beneath
+            .iter()
+            .filter(|piece| rules::piece_color(piece) == color)
+            .count() as i32;
+
+        if rules::piece_color(top) == color {
+            total += match *top {
+                Piece::Flatstone(_) => weights.flatstone,
+                Piece::StandingStone(_) => weights.standing_stone,
+                Piece::Capstone(_) => weights.capstone,
+            };
+            total += own_beneath * weights.captive;
+        } else {
+            total += own_beneath * weights.covered;
+        }
+    }
+
+    for group in resolution::road_groups(state, color) {
+        total += weights.group_span[span(&group).min(weights.group_span.len() - 1)];
+    }
+
+    total + rules::pieces_remaining_total(state, color) as i32 * weights.reserve
+}
+
+// The number of rows or columns the squares reach across, whichever is more
+fn span(squares: &[(usize, usize)]) -> usize {
+    let extent = |coordinates: Vec<usize>| {
+        let min = coordinates.iter().min().cloned().unwrap_or(0);
+        let max = coordinates.iter().max().cloned().unwrap_or(0);
+        max - min + 1
+    };
+
+    extent(squares.iter().map(|&(x, _)| x).collect())
+        .max(extent(squares.iter().map(|&(_, y)| y).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zero_sum::analysis::Evaluation as EvaluationTrait;
+
+    use crate::game::{ptn, tps};
+
+    #[test]
+    fn the_start_is_even() {
+        let state = rules::new_state(5).unwrap();
+
+        assert_eq!(WeightedEvaluator::default().evaluate(&state), Evaluation(0));
+    }
+
+    #[test]
+    fn scores_are_for_the_side_to_move() {
+        let weights = EvalWeights::default();
+        let white_to_move = tps::from_tps("1,1,x3/x5/x5/x5/2,x4 1 3").unwrap();
+        let black_to_move = tps::from_tps("1,1,x3/x5/x5/x5/2,x4 2 3").unwrap();
+
+        let score = evaluate(&white_to_move, &weights);
+        assert!(score > 0);
+        assert_eq!(evaluate(&black_to_move, &weights), -score);
+    }
+
+    #[test]
+    fn weights_change_the_score() {
+        let state = tps::from_tps("1,1,x3/x5/x5/x5/2,x4 1 3").unwrap();
+        let flats_only = EvalWeights {
+            flatstone: 100,
+            standing_stone: 0,
+            capstone: 0,
+            captive: 0,
+            covered: 0,
+            group_span: [0; 9],
+            reserve: 0,
+        };
+
+        assert_eq!(evaluate(&state, &flats_only), 100);
+    }
+
+    #[test]
+    fn a_finished_road_is_a_win() {
+        let start = rules::new_state(3).unwrap();
+        let moves = ["c3", "a1", "b1", "a3", "c1"];
+        let (state, _) = ptn::play_moves(&start, &moves, true).unwrap();
+
+        // White's a1, b1, and c1 make a road, and Black is to move, having lost
+        let evaluation = WeightedEvaluator::default().evaluate(&state);
+        assert!((-evaluation).is_win());
+        assert_eq!(evaluation, Evaluation(-(ROAD_WIN - 5)));
+    }
+
+    #[test]
+    fn road_scores_count_plies_from_the_root() {
+        let start = rules::new_state(3).unwrap();
+        let (root, _) = ptn::play_moves(&start, &["c3", "a1"], true).unwrap();
+
+        // A road on the game's fifth ply is three plies from a root at ply two
+        assert_eq!(
+            score_from_root(Evaluation(ROAD_WIN - 5), &root),
+            ROAD_WIN - 3
+        );
+        assert_eq!(
+            score_from_root(Evaluation(-(ROAD_WIN - 6)), &root),
+            -(ROAD_WIN - 4)
+        );
+        assert_eq!(score_from_root(Evaluation(250), &root), 250);
+        assert_eq!(score_from_root(Evaluation(0), &root), 0);
+    }
+}
@@ -35,9 +35,22 @@ pub trait Player {
 }
 
 pub use self::cli_player::CliPlayer;
+pub use self::evaluator::{score_from_root, EvalWeights, WeightedEvaluator};
+pub use self::negamax::{
+    negamax_search, negamax_search_deepening, negamax_search_parallel, negamax_search_shared,
+    plies_to_road, SharedTable, FLAT_WIN, ROAD_WIN,
+};
+pub use self::opening_book::OpeningBook;
 pub use self::playtak_player::PlayTakPlayer;
-pub use self::pvsearch_player::PvSearchPlayer;
+pub use self::pvsearch_player::{search_position, PvSearchPlayer, SearchOptions};
+pub use self::random_player::{RandomPlayer, SplitMix64, GOLDEN_GAMMA};
+pub use self::transposition_table::{Bound, TranspositionTable};
 
 mod cli_player;
+mod evaluator;
+mod negamax;
+mod opening_book;
 pub mod playtak_player;
 mod pvsearch_player;
+mod random_player;
+mod transposition_table;
@@ -0,0 +1,810 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use zero_sum::impls::tak::{Piece, Ply, Resolution, State};
+use zero_sum::State as StateTrait;
+
+use crate::game::{movegen, resolution, rules, zobrist, SearchResult};
+use crate::player::{evaluator, Bound, EvalWeights, TranspositionTable};
+
+// Scores are in centi-flats from the side to move's point of view: a flat ahead is 100, and a draw
+// is exactly 0.  A road win scores ROAD_WIN less the number of plies until the road is made, so a
+// faster road scores higher, and a flat win scores FLAT_WIN plus its margin, less its distance in
+// the same way.  Losses are the negation, so the longest loss scores highest.
+pub const ROAD_WIN: i32 = 1_000_000;
+pub const FLAT_WIN: i32 = 500_000;
+const INFINITY: i32 = i32::max_value();
+
+// The size of the table a search makes for itself when the caller doesn't pass one in
+const TABLE_MEGABYTES: usize = 16;
+
+pub type SharedTable = Arc<Mutex<TranspositionTable<i32>>>;
+
+// A ply's origin and destination squares and what kind of move it is, which is all the history
+// heuristic distinguishes between
+type HistoryKey = ((usize, usize), (usize, usize), u8);
+
+// Bookkeeping shared by every node of one search
+struct SearchContext {
+    nodes: u64,
+    // Two plies per distance from the root that most recently caused a beta cutoff there
+    killers: Vec<[Option<Ply>; 2]>,
+    // How often each kind of ply has caused cutoffs, weighted by the depth searched beneath them
+    history: HashMap<HistoryKey, u32>,
+    // Probed and stored at every node, and shared by the threads of a parallel search
+    table: SharedTable,
+    // Set when a helper thread's results are no longer wanted
+    stop: Option<Arc<AtomicBool>>,
+    // How many nodes this thread may search before it stops as if stop were raised
+    node_limit: Option<u64>,
+    // What the leaves are scored by
+    weights: EvalWeights,
+}
+
+impl SearchContext {
+    fn new(
+        table: SharedTable,
+        stop: Option<Arc<AtomicBool>>,
+        node_limit: Option<u64>,
+        weights: EvalWeights,
+    ) -> SearchContext {
+        SearchContext {
+            nodes: 0,
+            killers: Vec::new(),
+            history: HashMap::new(),
+            table: table,
+            stop: stop,
+            node_limit: node_limit,
+            weights: weights,
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        if self.node_limit.map_or(false, |limit| self.nodes >= limit) {
+            return true;
+        }
+
+        match self.stop {
+            Some(ref stop) => stop.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    // The table's best ply first, then the killers, then everything else by its history score
+    fn order(&mut self, plies: &mut [Ply], height: usize, table_ply: Option<&Ply>) {
+        if self.killers.len() <= height {
+            self.killers.resize(height + 1, [None, None]);
+        }
+
+        let killers = &self.killers[height];
+        let history = &self.history;
+
+        plies.sort_by_key(|ply| {
+            if table_ply == Some(ply) {
+                0
+            } else if killers[0].as_ref() == Some(ply) {
+                1
+            } else if killers[1].as_ref() == Some(ply) {
+                2
+            } else {
+                let score = history.get(&history_key(ply)).cloned().unwrap_or(0);
+                3 + (u32::max_value() - score) as u64
+            }
+        });
+    }
+
+    fn record_cutoff(&mut self, ply: &Ply, height: usize, depth: u32) {
+        let killers = &mut self.killers[height];
+        if killers[0].as_ref() != Some(ply) {
+            killers[1] = killers[0].take();
+            killers[0] = Some(ply.clone());
+        }
+
+        *self.history.entry(history_key(ply)).or_insert(0) += depth * depth;
+    }
+}
+
+fn history_key(ply: &Ply) -> HistoryKey {
+    let kind = match *ply {
+        Ply::Place {
+            piece: Piece::Flatstone(_),
+            ..
+        } => 0,
+        Ply::Place {
+            piece: Piece::StandingStone(_),
+            ..
+        } => 1,
+        Ply::Place {
+            piece: Piece::Capstone(_),
+            ..
+        } => 2,
+        Ply::Slide { .. } => 3,
+    };
+
+    (
+        rules::ply_origin(ply),
+        *rules::covered_squares(ply).last().unwrap(),
+        kind,
+    )
+}
+
+// A plain alpha-beta search that runs on the calling thread, for tools and experiments that don't
+// want PvSearchPlayer's channels.  Returns the best ply and its score for the side to move, or
+// None if the game is already over.  Positions are scored with evaluator::evaluate under the given
+// weights, as WeightedEvaluator scores them for PvSearch.  Each call starts from an empty table, so
+// the same position always gets the same result.
+pub fn negamax_search(state: &State, depth: u32, weights: &EvalWeights) -> Option<SearchResult> {
+    negamax_search_parallel(state, depth, 1, weights)
+}
+
+// negamax_search on several threads, Lazy SMP style: helper threads search the same root, half of
+// them a ply deeper, and share a transposition table with the main thread, whose result is the one
+// returned.  Which helper results reach the table first varies, so the result may vary from run to
+// run.  With one thread this is exactly negamax_search, which is deterministic.
+pub fn negamax_search_parallel(
+    state: &State,
+    depth: u32,
+    threads: usize,
+    weights: &EvalWeights,
+) -> Option<SearchResult> {
+    let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(
+        TABLE_MEGABYTES,
+    )));
+
+    negamax_search_shared(state, depth, threads, &table, None, None, weights)
+}
+
+// negamax_search_parallel with the caller's table, so that what one search learns carries over to
+// the next, e.g. from one iteration of a deepening search to the next.  Raising stop abandons the
+// search, which then returns the best of the root plies it finished as a lower bound, or None if it
+// finished none of them.  Running through node_limit does the same; only the main thread's nodes
+// count towards it, since the helpers are stopped when it finishes anyway.
+pub fn negamax_search_shared(
+    state: &State,
+    depth: u32,
+    threads: usize,
+    table: &SharedTable,
+    stop: Option<Arc<AtomicBool>>,
+    node_limit: Option<u64>,
+    weights: &EvalWeights,
+) -> Option<SearchResult> {
+    if state.check_resolution().is_some() {
+        return None;
+    }
+
+    let start_search = Instant::now();
+    let helpers_stop = Arc::new(AtomicBool::new(false));
+
+    let helpers = (1..threads)
+        .map(|index| {
+            let state = state.clone();
+            let mut context = SearchContext::new(
+                table.clone(),
+                Some(helpers_stop.clone()),
+                None,
+                weights.clone(),
+            );
+            let helper_depth = depth + (index % 2) as u32;
+
+            thread::spawn(move || {
+                search_root(&state, helper_depth, &mut context);
+                context.nodes
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut context = SearchContext::new(table.clone(), stop, node_limit, weights.clone());
+    let best = search_root(state, depth, &mut context);
+
+    helpers_stop.store(true, Ordering::Relaxed);
+    let helper_nodes = helpers
+        .into_iter()
+        .map(|helper| helper.join().unwrap_or(0))
+        .sum::<u64>();
+
+    best.map(|(ply, score, bound)| SearchResult {
+        best_ply: ply.clone(),
+        score: score,
+        bound: bound,
+        depth: depth.max(1),
+        principal_variation: vec![ply],
+        nodes: Some(context.nodes + helper_nodes),
+        elapsed: start_search.elapsed(),
+    })
+}
+
+// Searches one ply deeper at a time, up to max_depth or until stop is raised or the iterations
+// between them have used up node_limit, and returns the result of the deepest search that finished,
+// or of the interrupted one if what it finished already beats that.  Each iteration starts from the
+// table the last one filled, so the plies it found best are tried first.
+pub fn negamax_search_deepening(
+    state: &State,
+    max_depth: u32,
+    threads: usize,
+    table: &SharedTable,
+    stop: &Arc<AtomicBool>,
+    node_limit: Option<u64>,
+    weights: &EvalWeights,
+) -> Option<SearchResult> {
+    let start_search = Instant::now();
+    let mut nodes = 0;
+    let mut best = None;
+
+    for depth in 1..=max_depth.max(1) {
+        let remaining = node_limit.map(|limit| limit.saturating_sub(nodes));
+        if remaining == Some(0) {
+            break;
+        }
+
+        let stop = Some(stop.clone());
+        match negamax_search_shared(state, depth, threads, table, stop, remaining, weights) {
+            Some(result) => {
+                nodes += result.nodes.unwrap_or(0);
+
+                if result.bound == Bound::Exact {
+                    best = Some(result);
+                    continue;
+                }
+
+                // A lower bound is only worth more than the last finished search if it beats it
+                let improves = best
+                    .as_ref()
+                    .map_or(true, |last: &SearchResult| result.score > last.score);
+                if improves {
+                    best = Some(result);
+                }
+                break;
+            }
+            None => break,
+        }
+    }
+
+    best.map(|result| SearchResult {
+        nodes: Some(nodes),
+        elapsed: start_search.elapsed(),
+        ..result
+    })
+}
+
+// Returns the best root ply, its score, and whether that score is exact or, when the search was
+// stopped partway through the root plies, only a lower bound.
+fn search_root(
+    state: &State,
+    depth: u32,
+    context: &mut SearchContext,
+) -> Option<(Ply, i32, Bound)> {
+    let mut alpha = -INFINITY;
+    let mut best = None;
+    let mut bound = Bound::Exact;
+
+    context.nodes += 1;
+
+    // The last iteration of a deepening search leaves its best ply in the table
+    let hash = zobrist::zobrist_hash(state);
+    let table_ply = context
+        .table
+        .lock()
+        .unwrap()
+        .probe(hash)
+        .and_then(|entry| entry.best_ply.clone());
+
+    let mut plies = movegen::generate_plies(state);
+    context.order(&mut plies, 0, table_ply.as_ref());
+
+    for ply in plies {
+        let mut next = state.clone();
+        if next.execute_ply(Some(&ply)).is_err() {
+            continue;
+        }
+
+        let next_hash = zobrist::hash_after_ply(hash, state, &next, &ply);
+        let score = -negamax(
+            &next,
+            next_hash,
+            depth.max(1) - 1,
+            1,
+            -INFINITY,
+            -alpha,
+            context,
+        );
+
+        // The interrupted ply's score means nothing, but the ones before it still hold
+        if context.stopped() {
+            bound = Bound::Lower;
+            break;
+        }
+
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some((ply, score));
+        }
+    }
+
+    // The root is searched with a full window, so only a stop leaves its score inexact
+    if let Some((ref ply, score)) = best {
+        store(context, hash, depth, bound, score, 0, Some(ply.clone()));
+    }
+
+    best.map(|(ply, score)| (ply, score, bound))
+}
+
+// hash is the state's zobrist_hash, updated ply by ply instead of recomputed at every node.  height
+// is the distance from the root, which indexes the killer plies.
+fn negamax(
+    state: &State,
+    hash: u64,
+    depth: u32,
+    height: usize,
+    mut alpha: i32,
+    beta: i32,
+    context: &mut SearchContext,
+) -> i32 {
+    context.nodes += 1;
+
+    if let Some(resolution) = state.check_resolution() {
+        return terminal_score(state, &resolution, height);
+    }
+
+    if depth == 0 {
+        let plies = movegen::generate_plies(state);
+        if plies.is_empty() {
+            return terminal_score(state, &resolution::no_legal_ply_result(state), height);
+        }
+
+        // Look one ply past the horizon for a road the side to move can complete, so that a road
+        // threat left standing at the leaves isn't scored as a quiet position
+        if resolution::completes_road(state, &plies) {
+            return ROAD_WIN - (height as i32 + 1);
+        }
+
+        return evaluator::evaluate(state, &context.weights);
+    }
+
+    if context.stopped() {
+        return 0;
+    }
+
+    let mut table_ply = None;
+
+    if let Some(entry) = context.table.lock().unwrap().probe(hash) {
+        if entry.depth as u32 >= depth {
+            let score = from_table(entry.score, height);
+
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return beta,
+                Bound::Upper if score <= alpha => return alpha,
+                _ => (),
+            }
+        }
+
+        table_ply = entry.best_ply.clone();
+    }
+
+    let mut plies = movegen::generate_plies(state);
+    if plies.is_empty() {
+        return terminal_score(state, &resolution::no_legal_ply_result(state), height);
+    }
+
+    context.order(&mut plies, height, table_ply.as_ref());
+
+    let original_alpha = alpha;
+    let mut best_ply = None;
+
+    for ply in plies {
+        let mut next = state.clone();
+        if next.execute_ply(Some(&ply)).is_err() {
+            continue;
+        }
+
+        let next_hash = zobrist::hash_after_ply(hash, state, &next, &ply);
+        let score = -negamax(
+            &next,
+            next_hash,
+            depth - 1,
+            height + 1,
+            -beta,
+            -alpha,
+            context,
+        );
+
+        // A stopped search's scores are meaningless, so nothing more is recorded
+        if context.stopped() {
+            return 0;
+        }
+
+        if score >= beta {
+            context.record_cutoff(&ply, height, depth);
+            store(context, hash, depth, Bound::Lower, beta, height, Some(ply));
+            return beta;
+        }
+
+        if score > alpha {
+            alpha = score;
+            best_ply = Some(ply);
+        }
+    }
+
+    let bound = if alpha > original_alpha {
+        Bound::Exact
+    } else {
+        Bound::Upper
+    };
+    store(context, hash, depth, bound, alpha, height, best_ply);
+
+    alpha
+}
+
+fn store(
+    context: &SearchContext,
+    hash: u64,
+    depth: u32,
+    bound: Bound,
+    score: i32,
+    height: usize,
+    best_ply: Option<Ply>,
+) {
+    context.table.lock().unwrap().store(
+        hash,
+        depth.min(u8::max_value() as u32) as u8,
+        bound,
+        to_table(score, height),
+        best_ply,
+    );
+}
+
+// Road scores count plies from the root, so the table stores them counted from the node instead,
+// to be valid wherever the position turns up again
+fn to_table(score: i32, height: usize) -> i32 {
+    match plies_to_road(score) {
+        Some(_) if score > 0 => score + height as i32,
+        Some(_) => score - height as i32,
+        None => score,
+    }
+}
+
+fn from_table(score: i32, height: usize) -> i32 {
+    match plies_to_road(score) {
+        Some(_) if score > 0 => score - height as i32,
+        Some(_) => score + height as i32,
+        None => score,
+    }
+}
+
+// Wins closer to the root score higher, so the search takes the quickest win it can see and puts
+// off losses as long as it can.  A flat win's margin counts for more than its distance.
+fn terminal_score(state: &State, resolution: &Resolution, height: usize) -> i32 {
+    let side_to_move = rules::side_to_move(state);
+
+    let (winner, score) = match *resolution {
+        Resolution::Road(color) => (color, ROAD_WIN - height as i32),
+        Resolution::Flat(color) => (
+            color,
+            FLAT_WIN + resolution::flat_differential(state).abs() * 100 - height.min(99) as i32,
+        ),
+        Resolution::Draw => return 0,
+    };
+
+    if winner == side_to_move {
+        score
+    } else {
+        -score
+    }
+}
+
+// For a road win or loss score, the number of plies until the road is made, negative when it's the
+// opponent's road
+pub fn plies_to_road(score: i32) -> Option<i32> {
+    if score > FLAT_WIN * 3 / 2 {
+        Some(ROAD_WIN - score)
+    } else if score < -FLAT_WIN * 3 / 2 {
+        Some(-(ROAD_WIN + score))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ptn, tps};
+    use zero_sum::impls::tak::Color;
+
+    fn middlegame() -> State {
+        tps::from_tps("x,2,1,x/2S,1,21,x/x,1,2,x/1,x3 2 6").unwrap()
+    }
+
+    // White to move, with a1 and b1 needing only c1 for a road
+    fn road_in_one() -> State {
+        let start = rules::new_state(3).unwrap();
+        let (state, _) = ptn::play_moves(&start, &["c3", "a1", "b1", "a3"], true).unwrap();
+        state
+    }
+
+    // White to move, with Black's a2 to d2 needing only e2 for a road
+    fn road_threat() -> State {
+        tps::from_tps("1,x4/x,1,1,x,1/x5/2,2,2,2,x/x5 1 5").unwrap()
+    }
+
+    #[test]
+    fn finds_a_road_in_one() {
+        let result = negamax_search(&road_in_one(), 1, &EvalWeights::default()).unwrap();
+
+        assert!(result.best_ply == ptn::parse_ptn("c1", Color::White).unwrap());
+        assert_eq!(result.score, ROAD_WIN - 1);
+        assert_eq!(plies_to_road(result.score), Some(1));
+    }
+
+    #[test]
+    fn prefers_the_fastest_road() {
+        // Deeper searches find slower roads too, which must score lower than the one at hand
+        for depth in 2..=3 {
+            let result = negamax_search(&road_in_one(), depth, &EvalWeights::default()).unwrap();
+
+            assert!(result.best_ply == ptn::parse_ptn("c1", Color::White).unwrap());
+            assert_eq!(result.score, ROAD_WIN - 1);
+        }
+    }
+
+    // Alpha-beta over the plies in the order they're generated, with no table, to measure the
+    // search's move ordering against
+    fn unordered(
+        state: &State,
+        depth: u32,
+        height: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> (i32, u64) {
+        if let Some(resolution) = state.check_resolution() {
+            return (terminal_score(state, &resolution, height), 1);
+        }
+
+        let plies = movegen::generate_plies(state);
+        if plies.is_empty() {
+            return (
+                terminal_score(state, &resolution::no_legal_ply_result(state), height),
+                1,
+            );
+        }
+
+        if depth == 0 {
+            if resolution::completes_road(state, &plies) {
+                return (ROAD_WIN - (height as i32 + 1), 1);
+            }
+            return (evaluator::evaluate(state, &EvalWeights::default()), 1);
+        }
+
+        let mut nodes = 1;
+
+        for ply in plies {
+            let mut next = state.clone();
+            next.execute_ply(Some(&ply)).unwrap();
+
+            let (score, child_nodes) = unordered(&next, depth - 1, height + 1, -beta, -alpha);
+            nodes += child_nodes;
+
+            if -score >= beta {
+                return (beta, nodes);
+            }
+            alpha = alpha.max(-score);
+        }
+
+        (alpha, nodes)
+    }
+
+    #[test]
+    fn a_depth_one_search_sees_a_road_on_the_second_ply() {
+        let state = road_threat();
+
+        // After White's a1, Black's road lies one ply past a depth one search's leaves, so only the
+        // leaf extension finds it
+        let next = ptn::apply_ptn_move(&state, "a1").unwrap();
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let mut context = SearchContext::new(table, None, None, EvalWeights::default());
+        let score = negamax(
+            &next,
+            zobrist::zobrist_hash(&next),
+            0,
+            1,
+            -INFINITY,
+            INFINITY,
+            &mut context,
+        );
+        assert_eq!(score, ROAD_WIN - 2);
+        assert_eq!(plies_to_road(score), Some(2));
+
+        // Every ply but a stone on e2 loses to it
+        let result = negamax_search(&state, 1, &EvalWeights::default()).unwrap();
+        assert_eq!(rules::covered_squares(&result.best_ply), vec![(4, 1)]);
+        assert!(result.score > -ROAD_WIN / 2);
+    }
+
+    #[test]
+    fn move_ordering_searches_fewer_nodes() {
+        let state = middlegame();
+        let ordered = negamax_search(&state, 3, &EvalWeights::default()).unwrap();
+        let (score, nodes) = unordered(&state, 3, 0, -INFINITY, INFINITY);
+
+        assert_eq!(ordered.score, score);
+        assert!(
+            ordered.nodes.unwrap() < nodes,
+            "ordered search took {} nodes, unordered {}",
+            ordered.nodes.unwrap(),
+            nodes,
+        );
+    }
+
+    #[test]
+    fn one_thread_is_the_serial_search() {
+        let state = middlegame();
+        let serial = negamax_search(&state, 3, &EvalWeights::default()).unwrap();
+        let parallel = negamax_search_parallel(&state, 3, 1, &EvalWeights::default()).unwrap();
+
+        assert!(serial.best_ply == parallel.best_ply);
+        assert_eq!(serial.score, parallel.score);
+        assert_eq!(serial.nodes, parallel.nodes);
+    }
+
+    fn probe_bound(table: &SharedTable, state: &State) -> Bound {
+        let hash = zobrist::zobrist_hash(state);
+        table.lock().unwrap().probe(hash).unwrap().bound
+    }
+
+    #[test]
+    fn the_table_records_fail_highs_and_fail_lows_as_bounds() {
+        let state = middlegame();
+        let score = negamax_search(&state, 2, &EvalWeights::default())
+            .unwrap()
+            .score;
+
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let mut context = SearchContext::new(table.clone(), None, None, EvalWeights::default());
+        let high = negamax(
+            &state,
+            zobrist::zobrist_hash(&state),
+            2,
+            0,
+            score - 2,
+            score - 1,
+            &mut context,
+        );
+        assert_eq!(high, score - 1);
+        assert_eq!(probe_bound(&table, &state), Bound::Lower);
+
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let mut context = SearchContext::new(table.clone(), None, None, EvalWeights::default());
+        let low = negamax(
+            &state,
+            zobrist::zobrist_hash(&state),
+            2,
+            0,
+            score + 1,
+            score + 2,
+            &mut context,
+        );
+        assert_eq!(low, score + 1);
+        assert_eq!(probe_bound(&table, &state), Bound::Upper);
+
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let mut context = SearchContext::new(table.clone(), None, None, EvalWeights::default());
+        let exact = negamax(
+            &state,
+            zobrist::zobrist_hash(&state),
+            2,
+            0,
+            -INFINITY,
+            INFINITY,
+            &mut context,
+        );
+        assert_eq!(exact, score);
+        assert_eq!(probe_bound(&table, &state), Bound::Exact);
+    }
+
+    #[test]
+    fn a_search_stopped_before_any_root_ply_finishes_has_no_result() {
+        let state = middlegame();
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let stop = Arc::new(AtomicBool::new(true));
+
+        assert!(negamax_search_shared(
+            &state,
+            3,
+            1,
+            &table,
+            Some(stop),
+            None,
+            &EvalWeights::default()
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn a_deepening_search_answers_with_a_legal_ply_when_stopped() {
+        let state = middlegame();
+        let weights = EvalWeights::default();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Enough nodes to finish the depth one search, but not the depth two search after it
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let depth_one = negamax_search_shared(&state, 1, 1, &table, None, None, &weights).unwrap();
+        let node_limit = depth_one.nodes.unwrap() + 10;
+
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let result =
+            negamax_search_deepening(&state, 64, 1, &table, &stop, Some(node_limit), &weights)
+                .unwrap();
+
+        assert!(rules::validate_ply(&state, &result.best_ply).is_ok());
+        assert!(result.depth <= 2);
+    }
+
+    #[test]
+    fn leaves_are_scored_with_the_given_weights() {
+        let start = rules::new_state(5).unwrap();
+        let (state, _) = ptn::play_moves(&start, &["a1", "e5"], true).unwrap();
+        let flats_only = |flatstone| EvalWeights {
+            flatstone: flatstone,
+            standing_stone: 0,
+            capstone: 0,
+            captive: 0,
+            covered: 0,
+            group_span: [0; 9],
+            reserve: 0,
+        };
+
+        // White's best is another flat, which puts it a flat ahead at whatever a flat is worth
+        assert_eq!(
+            negamax_search(&state, 1, &flats_only(100)).unwrap().score,
+            100
+        );
+        assert_eq!(
+            negamax_search(&state, 1, &flats_only(300)).unwrap().score,
+            300
+        );
+    }
+    #[test]
+    fn a_deepening_search_stops_when_its_nodes_run_out() {
+        let state = middlegame();
+        let table = Arc::new(Mutex::new(TranspositionTable::with_megabytes(1)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let limit = 2000;
+
+        let result = negamax_search_deepening(
+            &state,
+            64,
+            1,
+            &table,
+            &stop,
+            Some(limit),
+            &EvalWeights::default(),
+        )
+        .unwrap();
+
+        assert!(rules::validate_ply(&state, &result.best_ply).is_ok());
+        // The node that finds the limit spent is the only one past it
+        assert!(result.nodes.unwrap() <= limit + 1);
+        assert!(result.depth < 64);
+    }
+}
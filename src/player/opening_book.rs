@@ -0,0 +1,134 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use zero_sum::impls::tak::{Ply, State};
+use zero_sum::State as StateTrait;
+
+use crate::game::{logger, movegen, rules, zobrist, Game};
+
+// Moves from a set of known games.  Positions are stored in their canonical orientation, and so are
+// the positions the book moves lead to, which lets a line stored in one orientation be played in
+// any of the others without having to transform the plies themselves.
+pub struct OpeningBook {
+    // Canonical position hash -> (canonical resulting position hash, number of games that played it)
+    positions: HashMap<u64, Vec<(u64, u32)>>,
+}
+
+impl OpeningBook {
+    pub fn new() -> OpeningBook {
+        OpeningBook {
+            positions: HashMap::new(),
+        }
+    }
+
+    // Reads a file of one or more PTN games
+    pub fn open(file_name: &str) -> Result<OpeningBook, String> {
+        let mut data = String::new();
+
+        match File::open(file_name) {
+            Ok(mut file) => {
+                if file.read_to_string(&mut data).is_err() {
+                    return Err(format!("Cannot read opening book: {}", file_name));
+                }
+            }
+            Err(_) => return Err(format!("Cannot open opening book: {}", file_name)),
+        }
+
+        let mut book = OpeningBook::new();
+        for game in split_games(&data) {
+            book.add_game(&game)?;
+        }
+
+        Ok(book)
+    }
+
+    pub fn add_game(&mut self, ptn: &str) -> Result<(), String> {
+        let (header, plies) = logger::parse_ptn(ptn)?;
+        let mut state = Game::into_state(header, Vec::new())?;
+
+        for ply in plies.iter() {
+            let position = zobrist::canonical_hash(&state);
+            rules::execute_validated(&mut state, ply)?;
+            let next = zobrist::canonical_hash(&state);
+
+            let moves = self.positions.entry(position).or_insert_with(Vec::new);
+            match moves.iter_mut().find(|&&mut (hash, _)| hash == next) {
+                Some(&mut (_, ref mut count)) => *count += 1,
+                None => moves.push((next, 1)),
+            }
+        }
+
+        Ok(())
+    }
+
+    // The legal ply leading to the book's most played continuation from this position, if the
+    // position is in the book
+    pub fn lookup(&self, state: &State) -> Option<Ply> {
+        let moves = self.positions.get(&zobrist::canonical_hash(state))?;
+
+        movegen::generate_plies(state)
+            .into_iter()
+            .filter_map(|ply| {
+                let mut next = state.clone();
+                next.execute_ply(Some(&ply)).ok()?;
+
+                let next = zobrist::canonical_hash(&next);
+                moves
+                    .iter()
+                    .find(|&&(hash, _)| hash == next)
+                    .map(|&(_, count)| (ply, count))
+            })
+            .max_by_key(|&(_, count)| count)
+            .map(|(ply, _)| ply)
+    }
+}
+
+// A new game starts at the first tag that follows a line of moves
+fn split_games(data: &str) -> Vec<String> {
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut in_moves = false;
+
+    for line in data.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with('[') {
+            if in_moves {
+                games.push(current);
+                current = String::new();
+                in_moves = false;
+            }
+        } else if !trimmed.is_empty() {
+            in_moves = true;
+        }
+
+        current.push_str(line);
+        current.push('\n');
+    }
+
+    if !current.trim().is_empty() {
+        games.push(current);
+    }
+
+    games
+}
@@ -26,11 +26,11 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use zero_sum::analysis::search::{PvSearch, PvSearchAnalysis, Search};
-use zero_sum::impls::tak::evaluator::StaticEvaluator;
 use zero_sum::impls::tak::*;
 use zero_sum::State as StateTrait;
 
 use crate::game::Message;
+use crate::player::WeightedEvaluator;
 
 use super::game_type::{GameType, ListedGame, Seek};
 use super::message_queue::MessageQueue;
@@ -533,13 +533,14 @@ fn evaluate_state(stream: &Arc<Mutex<TcpStream>>, state: &State, invoker: Option
     )
     .ok();
 
-    let mut search = PvSearch::<State, StaticEvaluator>::with_goal(StaticEvaluator, 10, 12.0);
+    let mut search =
+        PvSearch::<State, WeightedEvaluator>::with_goal(WeightedEvaluator::default(), 10, 12.0);
 
     let start_search = Instant::now();
     let analysis = search.search(&state, None);
     let pvsearch_analysis = analysis
         .as_any()
-        .downcast_ref::<PvSearchAnalysis<State, StaticEvaluator>>()
+        .downcast_ref::<PvSearchAnalysis<State, WeightedEvaluator>>()
         .unwrap();
     let elapsed_search = start_search.elapsed();
     let elapsed_search =
@@ -18,40 +18,125 @@
 //
 
 use std::any::Any;
-use std::sync::mpsc::{self, Sender};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use zero_sum::analysis::search::{PvSearch, PvSearchAnalysis, Search};
-use zero_sum::impls::tak::evaluator::StaticEvaluator;
+use zero_sum::analysis::Evaluator;
 use zero_sum::impls::tak::{Color, State};
+use zero_sum::State as StateTrait;
 
-use crate::game::Message;
-use crate::player::{PlayTakPlayer, Player};
+use crate::game::{movegen, rules, zobrist, Message, SearchLimit, SearchResult, TimePolicy};
+use crate::player::{
+    negamax_search_deepening, plies_to_road, score_from_root, Bound, EvalWeights, OpeningBook,
+    PlayTakPlayer, Player, SharedTable, TranspositionTable, WeightedEvaluator,
+};
+
+// How deep a negamax search without a depth limit may go.  It's meant to be stopped by its time or
+// node limit long before this.
+const PARALLEL_MAX_DEPTH: u32 = 64;
+
+// Engine settings that can change between searches, e.g. from TEI's setoption
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    pub hash_size: usize,
+    // More than one thread searches with negamax_search_parallel instead of PvSearch, which can
+    // only use one
+    pub threads: usize,
+    // How many candidate lines to report.  Every line past the first costs a search of each root
+    // ply.
+    pub multipv: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            hash_size: 64,
+            threads: 1,
+            multipv: 1,
+        }
+    }
+}
 
 pub struct PvSearchPlayer {
-    pvsearch: Arc<Mutex<PvSearch<State, StaticEvaluator>>>,
+    pvsearch: Arc<Mutex<PvSearch<State, WeightedEvaluator>>>,
+    // For the searches a request's limit calls for in place of pvsearch
+    weights: EvalWeights,
+    options: Arc<Mutex<SearchOptions>>,
+    book: Arc<Mutex<Option<OpeningBook>>>,
+    // Limits every request that doesn't bring its own
+    time_policy: Arc<Mutex<Option<TimePolicy>>>,
     depth: u8,
     goal: u16,
 }
 
 impl PvSearchPlayer {
     pub fn with_depth(depth: u8) -> PvSearchPlayer {
-        PvSearchPlayer {
-            pvsearch: Arc::new(Mutex::new(PvSearch::with_depth(StaticEvaluator, depth))),
-            depth: depth,
-            goal: 0,
-        }
+        let weights = EvalWeights::default();
+        let pvsearch = PvSearch::with_depth(WeightedEvaluator::new(weights.clone()), depth);
+        PvSearchPlayer::new(pvsearch, weights, depth, 0)
     }
 
     pub fn with_goal(goal: u16) -> PvSearchPlayer {
+        PvSearchPlayer::with_weights(goal, EvalWeights::default())
+    }
+
+    // Evaluates positions with the given weights instead of the defaults, e.g. while tuning them
+    pub fn with_weights(goal: u16, weights: EvalWeights) -> PvSearchPlayer {
+        let pvsearch = PvSearch::with_goal(WeightedEvaluator::new(weights.clone()), goal, 12.0);
+        PvSearchPlayer::new(pvsearch, weights, 0, goal)
+    }
+
+    fn new(
+        pvsearch: PvSearch<State, WeightedEvaluator>,
+        weights: EvalWeights,
+        depth: u8,
+        goal: u16,
+    ) -> PvSearchPlayer {
         PvSearchPlayer {
-            pvsearch: Arc::new(Mutex::new(PvSearch::with_goal(StaticEvaluator, goal, 12.0))),
-            depth: 0,
+            pvsearch: Arc::new(Mutex::new(pvsearch)),
+            weights: weights,
+            options: Arc::new(Mutex::new(SearchOptions::default())),
+            book: Arc::new(Mutex::new(None)),
+            time_policy: Arc::new(Mutex::new(None)),
+            depth: depth,
             goal: goal,
         }
     }
+
+    // Thinks for as long as the policy allows, rather than to a depth or a goal
+    pub fn with_time_budget(policy: TimePolicy) -> PvSearchPlayer {
+        let player = PvSearchPlayer::with_goal(u16::max_value());
+        player.set_time_policy(policy);
+        player
+    }
+
+    // Takes effect from the next search, even after the player has been initialized
+    pub fn set_hash_size(&self, megabytes: usize) {
+        self.options.lock().unwrap().hash_size = megabytes;
+    }
+
+    pub fn set_threads(&self, threads: usize) {
+        self.options.lock().unwrap().threads = threads;
+    }
+
+    pub fn set_multipv(&self, lines: usize) {
+        self.options.lock().unwrap().multipv = lines.max(1);
+    }
+
+    // A Clock policy's remaining time is as given here, so update it as the game goes on
+    pub fn set_time_policy(&self, policy: TimePolicy) {
+        *self.time_policy.lock().unwrap() = Some(policy);
+    }
+
+    // Book moves are played instantly, without searching
+    pub fn set_opening_book(&self, book: OpeningBook) {
+        *self.book.lock().unwrap() = Some(book);
+    }
 }
 
 impl Player for PvSearchPlayer {
@@ -61,61 +146,251 @@ impl Player for PvSearchPlayer {
         opponent: &dyn Player,
     ) -> Result<Sender<Message>, String> {
         let pvsearch = self.pvsearch.clone();
+        let weights = self.weights.clone();
+        let options = self.options.clone();
+        let book = self.book.clone();
+        let time_policy = self.time_policy.clone();
+        let depth = self.depth;
+        let goal = self.goal;
         let (sender, receiver) = mpsc::channel();
         let vs_playtak = opponent.as_any().is::<PlayTakPlayer>();
 
         thread::spawn(move || {
             let mut color = None;
             let interrupt = Arc::new(Mutex::new(None));
+            // Only a parallel search uses a table, so it's made for the first one.  It's then
+            // shared by the search's threads and kept from one search to the next.
+            let mut table: Option<SharedTable> = None;
 
             for message in receiver.iter() {
+                // A limited request is an ordinary request with different search settings
+                let (message, limit) = match message {
+                    Message::MoveRequestLimited(state, limit) => {
+                        (Message::MoveRequest(state), Some(limit))
+                    }
+                    message => (message, None),
+                };
+
                 match message {
                     Message::GameStart(assigned_color) => color = Some(assigned_color),
                     Message::MoveRequest(state) => {
+                        let requested = Instant::now();
+                        // Only the player's own clock is charged; a limited request brings its own
+                        let clocked = limit.is_none();
+                        let limit = match limit {
+                            Some(limit) => Some(limit),
+                            None => time_policy
+                                .lock()
+                                .unwrap()
+                                .as_ref()
+                                .map(|policy| policy.limit(&state)),
+                        };
+
+                        let book_ply = match *book.lock().unwrap() {
+                            Some(ref book) => book.lookup(&state),
+                            None => None,
+                        };
+
+                        if let Some(ply) = book_ply {
+                            if clocked {
+                                charge_clock(&time_policy, requested.elapsed());
+                            }
+                            to_game
+                                .send((color.unwrap(), Message::MoveResponse(ply)))
+                                .ok();
+                            continue;
+                        }
+
+                        let hash = zobrist::zobrist_hash(&state);
+                        let threads = options.lock().unwrap().threads;
+
+                        // PvSearch doesn't count its nodes, so a node limit is searched with
+                        // negamax however many threads there are
+                        let node_limit = match limit {
+                            Some(SearchLimit::Nodes(nodes)) => Some(nodes),
+                            _ => None,
+                        };
+                        let negamax = threads > 1 || node_limit.is_some();
+
+                        let (pvsearch, depth) = match limit {
+                            Some(SearchLimit::Depth(limit_depth)) => (
+                                Arc::new(Mutex::new(PvSearch::with_depth(
+                                    WeightedEvaluator::new(weights.clone()),
+                                    limit_depth,
+                                ))),
+                                limit_depth,
+                            ),
+                            // As long a goal as PvSearch allows; the GUI's stop ends it
+                            Some(SearchLimit::Infinite) => (
+                                Arc::new(Mutex::new(PvSearch::with_goal(
+                                    WeightedEvaluator::new(weights.clone()),
+                                    u16::max_value(),
+                                    12.0,
+                                ))),
+                                0,
+                            ),
+                            Some(SearchLimit::Nodes(_)) => (pvsearch.clone(), 0),
+                            _ => (pvsearch.clone(), depth),
+                        };
+
+                        if negamax {
+                            let hash_size = options.lock().unwrap().hash_size;
+                            let resize = table.as_ref().map_or(true, |table| {
+                                table.lock().unwrap().megabytes() != hash_size
+                            });
+
+                            if resize {
+                                table = Some(Arc::new(Mutex::new(
+                                    TranspositionTable::with_megabytes(hash_size),
+                                )));
+                            }
+                        } else {
+                            // PvSearch doesn't fill the table, so what's in it is going stale
+                            table = None;
+                        }
+
+                        if let Some(ref table) = table {
+                            // A fixed-depth player has nothing to gain by searching a position
+                            // it has already searched as deeply, e.g. after an undo
+                            if let Some(entry) = table.lock().unwrap().probe(hash) {
+                                if depth != 0 && entry.depth >= depth && entry.bound == Bound::Exact
+                                {
+                                    // A hash collision could leave a ply from another
+                                    // position
+                                    let ply = entry
+                                        .best_ply
+                                        .as_ref()
+                                        .filter(|ply| rules::validate_ply(&state, ply).is_ok());
+
+                                    if let Some(ply) = ply {
+                                        to_game
+                                            .send((
+                                                color.unwrap(),
+                                                Message::MoveResponse(ply.clone()),
+                                            ))
+                                            .ok();
+                                        continue;
+                                    }
+                                }
+                            }
+                        }
+
                         let (interrupt_sender, interrupt_receiver) = mpsc::channel();
+
+                        // PvSearch stops short of its goal when the next iteration looks like it
+                        // would overshoot.  The parallel search can't tell, so it's simply stopped
+                        // at the goal.
+                        let time_limit = match limit {
+                            Some(SearchLimit::Time(time_limit)) => Some(time_limit),
+                            None if threads > 1 && depth == 0 => {
+                                Some(Duration::from_secs(goal as u64))
+                            }
+                            _ => None,
+                        };
+
+                        if let Some(time_limit) = time_limit {
+                            let interrupt_sender = interrupt_sender.clone();
+                            thread::spawn(move || {
+                                thread::sleep(time_limit);
+                                interrupt_sender.send(()).ok();
+                            });
+                        }
+
                         let interrupt = interrupt.clone();
                         *interrupt.lock().unwrap() = Some(interrupt_sender);
 
                         let pvsearch = pvsearch.clone();
+                        let table = table.clone();
                         let to_game = to_game.clone();
+                        let multipv = options.lock().unwrap().multipv;
+                        let weights = weights.clone();
+                        let time_policy = time_policy.clone();
 
                         thread::spawn(move || {
-                            let start_search = Instant::now();
-                            let analysis = pvsearch
-                                .lock()
-                                .unwrap()
-                                .search(&state, Some(interrupt_receiver));
-                            let pvsearch_analysis = analysis
-                                .as_any()
-                                .downcast_ref::<PvSearchAnalysis<State, StaticEvaluator>>()
-                                .unwrap();
-                            let elapsed_search = start_search.elapsed();
-
-                            let mut interrupt = interrupt.lock().unwrap();
-                            if interrupt.is_none() {
-                                return;
-                            } else {
-                                *interrupt = None;
+                            let interruption = Interruption::relay(interrupt_receiver);
+
+                            let result = match table {
+                                Some(ref table) if negamax => {
+                                    let max_depth = if depth != 0 {
+                                        depth as u32
+                                    } else {
+                                        PARALLEL_MAX_DEPTH
+                                    };
+
+                                    negamax_search_deepening(
+                                        &state,
+                                        max_depth,
+                                        threads,
+                                        table,
+                                        &interruption.stopped,
+                                        node_limit,
+                                        &weights,
+                                    )
+                                }
+                                _ => search_position(
+                                    &mut pvsearch.lock().unwrap(),
+                                    &state,
+                                    Some(interruption.receiver()),
+                                )
+                                .map(|(result, _)| result),
+                            };
+
+                            {
+                                let mut interrupt = interrupt.lock().unwrap();
+                                if interrupt.is_none() {
+                                    return;
+                                } else {
+                                    *interrupt = None;
+                                }
+                            }
+
+                            if clocked {
+                                charge_clock(&time_policy, requested.elapsed());
                             }
 
-                            if let Some(ply) = pvsearch_analysis.principal_variation.first() {
+                            if let Some(result) = result {
                                 println!(
                                     "[PVSearch] Decision time (depth {}): {:.3} seconds{}",
-                                    pvsearch_analysis.principal_variation.len(),
-                                    elapsed_search.as_secs() as f32
-                                        + elapsed_search.subsec_nanos() as f32 / 1_000_000_000.0,
+                                    result.depth,
+                                    result.elapsed.as_secs() as f32
+                                        + result.elapsed.subsec_nanos() as f32 / 1_000_000_000.0,
                                     if vs_playtak {
-                                        format!(", Evaluation: {}", pvsearch_analysis.evaluation)
+                                        format!(", Evaluation: {}", result.score)
                                     } else {
                                         String::new()
                                     },
                                 );
+
+                                let message = if multipv > 1 {
+                                    Message::MoveResponseMultiPv(search_lines(
+                                        &state,
+                                        result,
+                                        multipv,
+                                        &weights,
+                                        &interruption,
+                                    ))
+                                } else {
+                                    Message::MoveResponseSearch(result)
+                                };
+
+                                to_game.send((color.unwrap(), message)).ok();
+                            } else if let Some(ply) =
+                                movegen::generate_plies(&state).into_iter().next()
+                            {
+                                // Stopped before any root ply was searched, but the game still
+                                // needs a move
                                 to_game
-                                    .send((color.unwrap(), Message::MoveResponse(ply.clone())))
+                                    .send((color.unwrap(), Message::MoveResponse(ply)))
                                     .ok();
                             }
                         });
                     }
+                    Message::StopSearch => {
+                        // Unlike GameOver, leave the interrupt in place so the search still responds
+                        if let Some(ref interrupt_sender) = *interrupt.lock().unwrap() {
+                            interrupt_sender.send(()).ok();
+                        }
+                    }
                     Message::GameOver => {
                         let mut interrupt = interrupt.lock().unwrap();
                         if let Some(ref interrupt_sender) = *interrupt {
@@ -141,7 +416,9 @@ impl Player for PvSearchPlayer {
             } else {
                 String::new()
             },
-            if self.depth != 0 {
+            if self.time_policy.lock().unwrap().is_some() {
+                String::from(" - Time budget")
+            } else if self.depth != 0 {
                 format!(" - Depth: {}", self.depth)
             } else if self.goal != 0 {
                 format!(" - Goal: {}s", self.goal)
@@ -155,3 +432,231 @@ impl Player for PvSearchPlayer {
         self
     }
 }
+
+// Runs a single search synchronously, for callers that don't need the player's channels.  Returns
+// None if the search found no move.  The evaluation is returned as well, in the evaluator's own
+// type, alongside the result's integer score.
+pub fn search_position(
+    pvsearch: &mut PvSearch<State, WeightedEvaluator>,
+    state: &State,
+    interrupt: Option<Receiver<()>>,
+) -> Option<(SearchResult, <WeightedEvaluator as Evaluator>::Evaluation)> {
+    let start_search = Instant::now();
+    let analysis = pvsearch.search(state, interrupt);
+    let pvsearch_analysis = analysis
+        .as_any()
+        .downcast_ref::<PvSearchAnalysis<State, WeightedEvaluator>>()
+        .unwrap();
+    let elapsed = start_search.elapsed();
+
+    let best_ply = pvsearch_analysis.principal_variation.first()?.clone();
+
+    // Keep only the part of the variation that is legal from the root
+    let mut principal_variation = Vec::new();
+    let mut pv_state = state.clone();
+    for ply in pvsearch_analysis.principal_variation.iter() {
+        if rules::execute_validated(&mut pv_state, ply).is_err() {
+            break;
+        }
+        principal_variation.push(ply.clone());
+    }
+
+    let score = score_from_root(pvsearch_analysis.evaluation, state);
+
+    Some((
+        SearchResult {
+            best_ply: best_ply,
+            score: score,
+            // PvSearch searches the root with a full window and reports the evaluation of its last
+            // completed iteration, never of one it was interrupted in
+            bound: Bound::Exact,
+            depth: pvsearch_analysis.principal_variation.len() as u32,
+            principal_variation: principal_variation,
+            // zero_sum's analysis doesn't report how many nodes it searched
+            nodes: None,
+            elapsed: elapsed,
+        },
+        pvsearch_analysis.evaluation,
+    ))
+}
+
+// Charges a move's time to the player's clock, if it plays on one
+fn charge_clock(time_policy: &Mutex<Option<TimePolicy>>, elapsed: Duration) {
+    if let Some(ref mut policy) = *time_policy.lock().unwrap() {
+        policy.charge(elapsed);
+    }
+}
+
+// Passes one interrupt on to each of the searches made for a move in turn.  PvSearch needs a
+// receiver of its own, while the parallel search and search_lines check the flag.
+struct Interruption {
+    stopped: Arc<AtomicBool>,
+    // The receiving end belongs to the PvSearch running now, if any
+    current: Arc<Mutex<Option<Sender<()>>>>,
+}
+
+impl Interruption {
+    fn relay(interrupt: Receiver<()>) -> Interruption {
+        let interruption = Interruption {
+            stopped: Arc::new(AtomicBool::new(false)),
+            current: Arc::new(Mutex::new(None)),
+        };
+
+        let stopped = interruption.stopped.clone();
+        let current = interruption.current.clone();
+        thread::spawn(move || {
+            while interrupt.recv().is_ok() {
+                stopped.store(true, Ordering::Relaxed);
+                if let Some(ref sender) = *current.lock().unwrap() {
+                    sender.send(()).ok();
+                }
+            }
+        });
+
+        interruption
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    // An interrupt for the next PvSearch, already raised if the search has been stopped
+    fn receiver(&self) -> Receiver<()> {
+        let (sender, receiver) = mpsc::channel();
+        let mut current = self.current.lock().unwrap();
+
+        if self.is_stopped() {
+            sender.send(()).ok();
+        }
+        *current = Some(sender);
+
+        receiver
+    }
+}
+
+// A child position's score from its parent's point of view, where a road is one ply further away
+fn parent_score(score: i32) -> i32 {
+    match plies_to_road(score) {
+        Some(_) if score > 0 => 1 - score,
+        Some(_) => -score - 1,
+        None => -score,
+    }
+}
+
+// The best line, followed by the best of the other root plies, up to the given number of lines.
+// PvSearch can't keep several lines at the root, so each other ply is scored by a search of the
+// position after it, one ply shallower than the best line.  A ply that ends the game is scored as
+// it stands.  Once the move's search is stopped, only the lines already finished are returned.
+fn search_lines(
+    state: &State,
+    best: SearchResult,
+    lines: usize,
+    weights: &EvalWeights,
+    interruption: &Interruption,
+) -> Vec<SearchResult> {
+    let depth = (best.depth.max(2) - 1).min(u8::max_value() as u32) as u8;
+    let evaluator = WeightedEvaluator::new(weights.clone());
+
+    let mut others = Vec::new();
+
+    for ply in movegen::generate_plies(state) {
+        if ply == best.best_ply {
+            continue;
+        }
+
+        let mut next = state.clone();
+        if next.execute_ply(Some(&ply)).is_err() {
+            continue;
+        }
+
+        if next.check_resolution().is_some() {
+            let score = score_from_root(evaluator.evaluate(&next), &next);
+            others.push(SearchResult {
+                best_ply: ply.clone(),
+                score: parent_score(score),
+                bound: Bound::Exact,
+                depth: 1,
+                principal_variation: vec![ply],
+                nodes: None,
+                elapsed: Duration::from_secs(0),
+            });
+            continue;
+        }
+
+        // Plies that end the game are still listed after a stop, since they cost nothing to score
+        if interruption.is_stopped() {
+            continue;
+        }
+
+        let mut pvsearch = PvSearch::with_depth(evaluator.clone(), depth);
+        let reply = search_position(&mut pvsearch, &next, Some(interruption.receiver()));
+
+        // An interrupted search is shallower than the rest, so it isn't comparable
+        if interruption.is_stopped() {
+            continue;
+        }
+
+        if let Some((reply, _)) = reply {
+            let mut principal_variation = vec![ply.clone()];
+            principal_variation.extend(reply.principal_variation);
+
+            others.push(SearchResult {
+                best_ply: ply,
+                score: parent_score(reply.score),
+                bound: reply.bound.flip(),
+                depth: reply.depth + 1,
+                principal_variation: principal_variation,
+                nodes: None,
+                elapsed: reply.elapsed,
+            });
+        }
+    }
+
+    others.sort_by_key(|line| Reverse(line.score));
+
+    let mut all = vec![best];
+    all.extend(others.into_iter().take(lines - 1));
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ptn;
+    use crate::player::ROAD_WIN;
+
+    #[test]
+    fn a_stopped_search_still_lists_the_plies_that_win() {
+        let start = rules::new_state(3).unwrap();
+        let (state, _) = ptn::play_moves(&start, &["c3", "a1", "b1", "a3"], true).unwrap();
+
+        let best = SearchResult {
+            best_ply: ptn::parse_ptn("b2", Color::White).unwrap(),
+            score: 0,
+            bound: Bound::Exact,
+            depth: 2,
+            principal_variation: Vec::new(),
+            nodes: None,
+            elapsed: Duration::from_secs(0),
+        };
+        let stopped = Interruption {
+            stopped: Arc::new(AtomicBool::new(true)),
+            current: Arc::new(Mutex::new(None)),
+        };
+
+        let lines = search_lines(&state, best, 4, &EvalWeights::default(), &stopped);
+
+        // White's c1 completes the road a1-b1-c1; nothing else was searched
+        let road = ptn::parse_ptn("c1", Color::White).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].best_ply == road);
+        assert_eq!(lines[1].score, ROAD_WIN - 1);
+    }
+
+    #[test]
+    fn a_child_score_is_a_ply_further_from_a_road() {
+        assert_eq!(parent_score(-ROAD_WIN), ROAD_WIN - 1);
+        assert_eq!(parent_score(ROAD_WIN - 1), -(ROAD_WIN - 2));
+        assert_eq!(parent_score(150), -150);
+    }
+}
@@ -0,0 +1,153 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::any::Any;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zero_sum::impls::tak::Color;
+
+use crate::game::{movegen, Message};
+use crate::player::Player;
+
+// Plays uniformly random legal plies, as a weak baseline to measure the search against.  With the
+// same seed it plays the same game against the same opponent.
+pub struct RandomPlayer {
+    seed: u64,
+}
+
+impl RandomPlayer {
+    pub fn new() -> RandomPlayer {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+
+        RandomPlayer::with_seed(nanos)
+    }
+
+    pub fn with_seed(seed: u64) -> RandomPlayer {
+        RandomPlayer { seed: seed }
+    }
+
+    // For reproducing a game played with a time-based seed
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for RandomPlayer {
+    fn default() -> RandomPlayer {
+        RandomPlayer::new()
+    }
+}
+
+impl Player for RandomPlayer {
+    fn initialize(
+        &mut self,
+        to_game: Sender<(Color, Message)>,
+        _: &dyn Player,
+    ) -> Result<Sender<Message>, String> {
+        let (sender, receiver) = mpsc::channel();
+        let mut rng = SplitMix64::new(self.seed);
+
+        thread::spawn(move || {
+            let mut color = None;
+
+            for message in receiver.iter() {
+                match message {
+                    Message::GameStart(assigned_color) => color = Some(assigned_color),
+                    Message::MoveRequest(state) | Message::MoveRequestLimited(state, _) => {
+                        let mut plies = movegen::generate_plies(&state);
+                        if plies.is_empty() {
+                            continue;
+                        }
+
+                        let index = rng.below(plies.len());
+                        to_game
+                            .send((
+                                color.unwrap(),
+                                Message::MoveResponse(plies.swap_remove(index)),
+                            ))
+                            .ok();
+                    }
+                    Message::UndoRequest => {
+                        to_game.send((color.unwrap(), Message::UndoAccept)).ok();
+                    }
+                    Message::GameOver => break,
+                    _ => (),
+                }
+            }
+        });
+
+        Ok(sender)
+    }
+
+    fn get_name(&self) -> String {
+        format!("Random (Seed: {})", self.seed)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// The golden ratio in fixed point, which SplitMix64 steps its state by
+pub const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// A small, fast generator; statistical quality beyond this doesn't matter for choosing plies
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GOLDEN_GAMMA);
+        SplitMix64::mix(self.0)
+    }
+
+    // The finalizer that turns the generator's state into its output.  It's also a good hash of a
+    // single integer, e.g. for deriving Zobrist keys.
+    pub fn mix(mut z: u64) -> u64 {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number from 0 up to but not including the bound, which must not be 0
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitmix64_matches_the_reference_sequence() {
+        let mut rng = SplitMix64::new(0);
+
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+}
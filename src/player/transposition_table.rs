@@ -0,0 +1,110 @@
+//
+// This file is part of Takkerus.
+//
+// Takkerus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Takkerus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Takkerus. If not, see <http://www.gnu.org/licenses/>.
+//
+// Copyright 2016-2017 Chris Foster
+//
+
+use std::mem;
+
+use zero_sum::impls::tak::Ply;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+impl Bound {
+    // The same bound seen from the other side, as when a score is negated
+    pub fn flip(self) -> Bound {
+        match self {
+            Bound::Exact => Bound::Exact,
+            Bound::Lower => Bound::Upper,
+            Bound::Upper => Bound::Lower,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry<E> {
+    pub key: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: E,
+    pub best_ply: Option<Ply>,
+}
+
+// A fixed-size table of search results keyed by Zobrist hash.  Each hash maps to a single slot,
+// and the full key is stored alongside the entry so that a collision is never mistaken for a hit.
+pub struct TranspositionTable<E> {
+    entries: Vec<Option<Entry<E>>>,
+    megabytes: usize,
+}
+
+impl<E> TranspositionTable<E> {
+    pub fn with_megabytes(megabytes: usize) -> TranspositionTable<E> {
+        let slots = megabytes * 1024 * 1024 / mem::size_of::<Option<Entry<E>>>();
+
+        let mut entries = Vec::new();
+        entries.resize_with(slots.max(1), || None);
+
+        TranspositionTable {
+            entries: entries,
+            megabytes: megabytes,
+        }
+    }
+
+    pub fn megabytes(&self) -> usize {
+        self.megabytes
+    }
+
+    pub fn probe(&self, key: u64) -> Option<&Entry<E>> {
+        match self.entries[self.index(key)] {
+            Some(ref entry) if entry.key == key => Some(entry),
+            _ => None,
+        }
+    }
+
+    // Replaces whatever is in the slot unless it holds a result from a deeper search
+    pub fn store(&mut self, key: u64, depth: u8, bound: Bound, score: E, best_ply: Option<Ply>) {
+        let index = self.index(key);
+
+        if let Some(ref entry) = self.entries[index] {
+            if entry.depth > depth {
+                return;
+            }
+        }
+
+        self.entries[index] = Some(Entry {
+            key: key,
+            depth: depth,
+            bound: bound,
+            score: score,
+            best_ply: best_ply,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}